@@ -91,7 +91,10 @@ POOL_ID=$(stellar contract invoke \
   --reward_token $REWARD_TOKEN_ADDRESS \
   --total_rewards 1000000000000 \
   --max_apy 1500 \
-  --distribution_days 30)
+  --distribution_days 30 \
+  --schedule Constant \
+  --mode Continuous \
+  --conversion Parity)
 
 echo "✅ Pool de exemplo criada com ID: $POOL_ID"
 
@@ -1,9 +1,97 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 use soroban_sdk::{
     contract, contractimpl, contracttype, contractclient, symbol_short,
-    Address, Env, Symbol, Vec, Map
+    xdr::ToXdr, Address, Bytes, BytesN, Env, IntoVal, Map, String, Symbol, TryFromVal, Val, Vec
 };
 
+mod math;
+
+use math::{mul_div_ceil, mul_div_floor, mul_div_rem, Rate, Rounding};
+
+// Ciclo de vida de uma pool.
+// Scheduled/Active/Ended são derivados do tempo do ledger; Paused, Cancelled e Finalized
+// são definidos explicitamente pelas transições do contrato.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PoolStatus {
+    Scheduled,
+    Active,
+    Paused,
+    Ended,
+    Cancelled,
+    Finalized,
+}
+
+// Curva de emissão da pool ao longo da sua vida
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EmissionSchedule {
+    // Distribuição diária constante (total / dias)
+    Constant,
+    // Decaimento linear até a taxa final, em bps da taxa inicial
+    LinearDecay(u32),
+    // Tabela por época: duração da época em dias e valor emitido em cada época
+    Stepwise(u32, Vec<i128>),
+    // Emissão cai pela metade a cada N dias
+    Halving(u32),
+}
+
+// Conversão entre unidades de stake e unidades de recompensa, usada para aplicar o APY
+// quando os tokens diferem em preço ou decimais
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConversionRate {
+    // Mesma unidade (1:1)
+    Parity,
+    // Unidades de recompensa por unidade de stake, escalado por RATE_PRECISION
+    Fixed(i128),
+    // Taxa lida de um contrato de oráculo no momento do acúmulo
+    Oracle(Address),
+    // APY em dólar: razão entre os preços USD do stake e da recompensa num oráculo SEP-40
+    UsdPrice(Address),
+}
+
+// Ativo consultado no oráculo de preços (SEP-40)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+// Preço retornado pelo oráculo SEP-40, com `decimals()` casas decimais
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+// Modelo de acúmulo do APY
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccrualModel {
+    // Juros simples: o APY é aplicado linearmente ao longo do ano
+    Simple,
+    // Juros compostos diários: quem reinveste todo dia recebe exatamente o APY anunciado
+    Compounding,
+}
+
+// Forma de distribuição das recompensas
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DistributionMode {
+    // Acúmulo contínuo por segundo
+    Continuous,
+    // Épocas diárias: a emissão de cada época é rateada pelos saldos no fechamento da época
+    EpochSnapshot,
+    // Direitos calculados off-chain e publicados como raiz Merkle por época
+    Merkle,
+    // Direitos calculados off-chain e resgatados com vouchers assinados (ed25519)
+    Voucher,
+}
+
 // Estrutura para representar uma pool de recompensas
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -12,15 +100,42 @@ pub struct Pool {
     pub owner: Address,
     pub stake_token: Address,   // Token que define a participação (ex: KALE)
     pub reward_token: Address,  // Token de recompensa (ex: USDC/USDT/KALE)
+    pub stake_decimals: u32,    // Casas decimais do token de stake, lidas na criação
+    pub reward_decimals: u32,   // Casas decimais do token de recompensa, lidas na criação
+    pub stake_symbol: String,
+    pub reward_symbol: String,
     pub total_rewards: i128,    // Total depositado de recompensas disponível
-    pub max_apy: u32,           // APY em pontos base (ex: 1500 = 15%)
+    pub max_apy: u32,           // APY em pontos base (ex: 1500 = 15%), arredondado para baixo
+    pub apy_rate: i128,         // APY em alta precisão (escala APY_RATE_SCALE), usado no acúmulo
+    pub next_apy_rate: i128,    // APY proposto, em alta precisão (0 = nenhuma mudança pendente)
+    pub accrual: AccrualModel,
+    pub accrual_rate: i128,     // Taxa anual simples aplicada pelo acumulador (escala Rate::SCALE)
+    pub next_apy_at: u64,       // Quando o APY proposto passa a valer
+    pub apy_notice_period: u64, // Aviso prévio mínimo para mudanças de APY (segundos)
     pub distribution_days: u32,
     pub daily_distribution: i128,
+    pub schedule: EmissionSchedule,
+    pub mode: DistributionMode,
+    pub conversion: ConversionRate,
+    pub streams: Vec<RewardStream>, // Fluxos adicionais de recompensa (ex: KALE + USDC)
+    pub epochs_closed: u32,     // Épocas já fechadas e rateadas (modo EpochSnapshot)
+    pub merkle_epoch: u32,      // Última época com raiz Merkle publicada (modo Merkle)
     pub distributed_amount: i128,
+    pub escrowed: i128,         // Recompensas depositadas e ainda custodiadas pelo contrato
+    pub deposited: i128,        // Total já depositado pelo dono e por patrocinadores
+    pub sponsored: i128,        // Parte de `deposited` vinda de patrocinadores
+    pub reserved_rewards: i128, // Recompensas separadas na finalização e ainda não resgatadas
+    pub restaked: i128,         // Recompensas reinvestidas como stake, mantidas pelo contrato
+    pub emitted: i128,          // Recompensas já creditadas pelo acumulador
+    pub last_rate: i128,        // Última taxa de conversão aceita de um oráculo (0 = nenhuma)
+    pub last_rate_at: u64,      // Quando `last_rate` foi aceita
+    pub reward_per_token: i128, // Acumulador de recompensa por unidade delegada (escala `acc_precision`)
+    pub last_update: u64,       // Último checkpoint do acumulador
     pub total_delegated: i128,  // Soma das delegações atuais (sync)
     pub start_time: u64,
     pub end_time: u64,
-    pub is_active: bool,
+    pub finalized_at: u64,
+    pub status: PoolStatus,
 }
 
 // Estrutura para representar uma delegação de usuário
@@ -32,20 +147,203 @@ pub struct Delegation {
     pub amount: i128,
     pub timestamp: u64,
     pub last_claim: u64,
+    pub accrued: i128,          // Recompensas já contabilizadas e ainda não resgatadas
+    pub reward_per_token_paid: i128, // Valor do acumulador da pool no último checkpoint
+    pub reward_remainder: i128, // Fração de recompensa ainda não creditada (escala `acc_precision`)
+    pub compounded: i128,       // Stake vindo de recompensas reinvestidas, somado a `amount`
+    pub streams: Vec<StreamCheckpoint>, // Checkpoints nos fluxos adicionais, pelo índice do fluxo
+}
+
+// Fluxo adicional de recompensa da pool, com token, orçamento, curva e acumulador próprios.
+// Fluxos acumulam só pela emissão da curva, sem o limite de APY do fluxo principal.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RewardStream {
+    pub token: Address,
+    pub decimals: u32,          // Casas decimais do token do fluxo, lidas na criação
+    pub total_rewards: i128,
+    pub daily_distribution: i128,
+    pub schedule: EmissionSchedule,
+    pub escrowed: i128,         // Tokens do fluxo custodiados pelo contrato
+    pub emitted: i128,          // Recompensas do fluxo já atribuídas aos delegadores
+    pub distributed: i128,      // Recompensas do fluxo já pagas
+    pub reward_per_token: i128, // Acumulador do fluxo (escala `acc_precision`)
+    pub last_update: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub ended: bool,
+}
+
+// Checkpoint de uma delegação em um fluxo adicional
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamCheckpoint {
+    pub reward_per_token_paid: i128,
+    pub accrued: i128,
+    pub remainder: i128,
+}
+
+// Registro de uma época no modo EpochSnapshot
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EpochSnapshot {
+    pub epoch: u32,
+    pub total_delegated: i128,  // Total delegado registrado para a época
+    pub user_balance: i128,     // Saldo do usuário registrado para a época
+    pub emission: i128,         // Emissão da curva na época
+    pub closed: bool,
+}
+
+// Mudança de APY agendada
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ApyChange {
+    pub new_apy: u32,
+    pub new_apy_rate: i128,
+    pub effective_at: u64,
+}
+
+// Visão de uma delegação no portfólio do usuário
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegationInfo {
+    pub delegation: Delegation,
+    pub pending_rewards: i128,
+    pub effective_apy: u32,     // APY efetivo estimado em pontos base
+    pub pool_status: PoolStatus,
+}
+
+// Critérios de filtro para a listagem de pools (todos devem ser atendidos)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PoolFilter {
+    Status(PoolStatus),
+    Owner(Address),
+    StakeToken(Address),
+    RewardToken(Address),
+}
+
+// Índices de pools mantidos na criação
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PoolIndexKey {
+    Owner(Address),
+    StakeToken(Address),
+}
+
+// Chaves de armazenamento persistente, uma entrada por registro
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DataKey {
+    Pool(u64),                         // Pool por ID
+    AttestedStake(u64),                // Pool cujo token de stake não tem contrato consultável
+    OptIn(Address, u64),               // (usuário, pool) -> opt-in registrado
+    UserPools(Address),                // Pools em que o usuário fez opt-in
+    Delegation(Address, u64),          // (usuário, pool) -> delegação
+    UserDelegations(Address),          // Pools em que o usuário tem delegação
+    PoolIndexLen(PoolIndexKey),        // Número de entradas de um índice de pools
+    PoolIndexEntry(PoolIndexKey, u32), // (índice, posição) -> ID da pool, em ordem de criação
+    EpochTotal(u64, u32),              // (pool, época) -> (total delegado, época do registro anterior)
+    EpochBalance(Address, u64, u32),   // (usuário, pool, época) -> (saldo, época do registro anterior)
+    LastEpochTotal(u64),               // Época do registro mais recente do total da pool
+    LastEpochBalance(Address, u64),    // Época do registro mais recente do saldo do usuário
 }
 
 // Chaves de armazenamento
-const POOLS: Symbol = symbol_short!("POOLS");
-const DELEGATIONS: Symbol = symbol_short!("DELEGS");
 const POOL_COUNT: Symbol = symbol_short!("PCOUNT");
 const ADMIN: Symbol = symbol_short!("ADMIN");
-const OPTIN: Symbol = symbol_short!("OPTIN");
+const DISTRIBUTORS: Symbol = symbol_short!("DISTRIB");
+const MERKLE_ROOTS: Symbol = symbol_short!("MROOTS");
+const MERKLE_CLAIMED: Symbol = symbol_short!("MCLAIMED");
+const VOUCHER_SIGNERS: Symbol = symbol_short!("VSIGNERS");
+const VOUCHER_NONCES: Symbol = symbol_short!("VNONCES");
+const VOUCHER_CLAIMED: Symbol = symbol_short!("VCLAIMED");
+const BALANCE_ORACLES: Symbol = symbol_short!("BORACLES");
+const ATTESTED_AT: Symbol = symbol_short!("ATTESTAT");
+const AUTO_COMPOUND: Symbol = symbol_short!("AUTOCOMP");
+const SPONSORS: Symbol = symbol_short!("SPONSORS");
+const PROTOCOL_FEE: Symbol = symbol_short!("PFEE");
+const FEES_ACCRUED: Symbol = symbol_short!("FEES");
+
+// Precisão do acumulador de recompensa por unidade delegada
+const ACC_PRECISION: i128 = 1_000_000_000_000;
+const SECONDS_PER_YEAR: i128 = 365 * 86400;
+
+// Períodos de composição por ano no modelo AccrualModel::Compounding (diário)
+const COMPOUNDING_PERIODS: u32 = 365;
+
+// Escala do APY em alta precisão (1_000_000_000 = 100% ao ano)
+const APY_RATE_SCALE: i128 = 1_000_000_000;
+
+// Escala das taxas de conversão stake -> recompensa (1.0 = 1_000_000_000)
+const RATE_PRECISION: i128 = 1_000_000_000;
+
+// Casas decimais assumidas quando o ativo não tem contrato de token consultável (padrão Stellar)
+const DEFAULT_TOKEN_DECIMALS: u32 = 7;
+
+// Máximo de casas decimais aceito para tokens de stake e de recompensa
+const MAX_TOKEN_DECIMALS: u32 = 18;
+
+// TTL das entradas persistentes, em ledgers (~5 s cada): a cada leitura ou gravação,
+// entradas com menos de ~30 dias restantes são renovadas para ~120 dias
+const PERSISTENT_TTL_THRESHOLD: u32 = 30 * 17_280;
+const PERSISTENT_TTL_EXTEND: u32 = 120 * 17_280;
+
+// Idade máxima de um preço do oráculo SEP-40 (15 minutos)
+const MAX_PRICE_AGE: u64 = 900;
+
+// Variação máxima da taxa em dólar por dia desde a última taxa aceita (20%)
+const MAX_RATE_DEVIATION_BPS: i128 = 2000;
+const RATE_DEVIATION_PERIOD: i128 = 86400;
+
+// Duração de uma época no modo EpochSnapshot (1 dia)
+const EPOCH_LENGTH: u64 = 86400;
+
+// Aviso prévio padrão para mudanças de APY (7 dias)
+const DEFAULT_APY_NOTICE_PERIOD: u64 = 7 * 86400;
+
+// Antecedência máxima para agendar o início de uma pool (90 dias)
+const MAX_START_DELAY: u64 = 90 * 86400;
+
+// Carência após a finalização antes que o dono possa retirar as sobras (7 dias)
+const FINALIZE_GRACE_PERIOD: u64 = 7 * 86400;
+
+// Janela de resgate após a finalização nos modos Merkle e Voucher, em que os direitos não são
+// conhecidos on-chain e todo o custodiado fica reservado (90 dias)
+const CUMULATIVE_CLAIM_WINDOW: u64 = 90 * 86400;
+
+// Idade máxima de um atestado de saldos do oráculo (1 hora)
+const MAX_ATTESTATION_AGE: u64 = 3600;
+
+// Taxa máxima do protocolo sobre recompensas pagas (pontos base, 10%)
+const MAX_PROTOCOL_FEE_BPS: u32 = 1000;
+
+// Tamanho máximo de página nas consultas paginadas
+const MAX_PAGE_SIZE: u32 = 50;
+
+// Máximo de pools percorridas por chamada de `list_pools`, com ou sem correspondência aos filtros
+const MAX_POOLS_SCANNED: u32 = 100;
 
 // Interface mínima do contrato padrão de token Soroban
 #[contractclient(name="TokenClient")]
 pub trait TokenInterface {
     fn balance(e: Env, id: Address) -> i128;
     fn transfer(e: Env, from: Address, to: Address, amount: i128);
+    fn decimals(e: Env) -> u32;
+    fn symbol(e: Env) -> String;
+}
+
+// Oráculo de conversão: unidades de recompensa por unidade de stake, escalado por RATE_PRECISION
+#[contractclient(name="RateOracleClient")]
+pub trait RateOracleInterface {
+    fn conversion_rate(e: Env, stake_token: Address, reward_token: Address) -> i128;
+}
+
+// Oráculo de preços no padrão SEP-40
+#[contractclient(name="PriceOracleClient")]
+pub trait PriceOracleInterface {
+    fn lastprice(e: Env, asset: Asset) -> Option<PriceData>;
+    fn decimals(e: Env) -> u32;
 }
 
 #[contract]
@@ -61,7 +359,8 @@ impl PoolRewardsContract {
         env.storage().instance().set(&POOL_COUNT, &0u64);
     }
 
-    /// Cria uma nova pool de recompensas
+    /// Cria uma nova pool de recompensas com o APY em pontos base (compatibilidade).
+    /// Equivale a `create_pool_with_rate` com `max_apy * 100_000`.
     pub fn create_pool(
         env: Env,
         owner: Address,
@@ -70,13 +369,46 @@ impl PoolRewardsContract {
         total_rewards: i128,
         max_apy: u32,
         distribution_days: u32,
+        start_time: Option<u64>,
+        schedule: EmissionSchedule,
+        mode: DistributionMode,
+        conversion: ConversionRate,
+    ) -> u64 {
+        assert!(max_apy > 0 && max_apy <= 10000, "APY must be between 0.01% and 100%");
+        Self::create_pool_with_rate(
+            env, owner, stake_token, reward_token, total_rewards, bps_to_apy_rate(max_apy),
+            distribution_days, start_time, schedule, mode, conversion,
+        )
+    }
+
+    /// Cria uma nova pool de recompensas.
+    /// `apy_rate` é o APY anual em escala 1e9 (1_000_000_000 = 100%).
+    /// `start_time` opcional agenda o início; até lá a pool fica Scheduled (opt-in e sync permitidos, sem acúmulo).
+    /// `schedule` define a curva de emissão do orçamento ao longo da vida da pool e
+    /// `mode` se as recompensas acumulam continuamente ou por épocas diárias.
+    pub fn create_pool_with_rate(
+        env: Env,
+        owner: Address,
+        stake_token: Address,
+        reward_token: Address,
+        total_rewards: i128,
+        apy_rate: i128,
+        distribution_days: u32,
+        start_time: Option<u64>,
+        schedule: EmissionSchedule,
+        mode: DistributionMode,
+        conversion: ConversionRate,
     ) -> u64 {
         owner.require_auth();
         
         // Validações
         assert!(total_rewards > 0, "Total rewards must be positive");
-        assert!(max_apy > 0 && max_apy <= 10000, "APY must be between 0.01% and 100%");
+        assert!(apy_rate > 0 && apy_rate <= APY_RATE_SCALE, "APY must be positive and at most 100%");
         assert!(distribution_days > 0, "Distribution days must be positive");
+        validate_schedule(&schedule, total_rewards);
+        if let ConversionRate::Fixed(rate) = conversion {
+            assert!(rate > 0, "Conversion rate must be positive");
+        }
         
         // Obter próximo ID da pool
         let pool_count: u64 = env.storage().instance().get(&POOL_COUNT).unwrap_or(0);
@@ -85,34 +417,85 @@ impl PoolRewardsContract {
         // Calcular distribuição diária
         let daily_distribution = total_rewards / (distribution_days as i128);
         
-        // Obter timestamp atual
+        // Início imediato ou agendado
         let current_time = env.ledger().timestamp();
-        let end_time = current_time + (distribution_days as u64 * 86400); // 86400 segundos = 1 dia
+        let start_time = start_time.unwrap_or(current_time);
+        assert!(start_time >= current_time, "Start time must not be in the past");
+        assert!(start_time <= current_time + MAX_START_DELAY, "Start time too far in the future");
+        let end_time = start_time + (distribution_days as u64 * 86400); // 86400 segundos = 1 dia
+        let status = if start_time > current_time { PoolStatus::Scheduled } else { PoolStatus::Active };
         
         // Criar nova pool
+        // Metadados dos tokens ficam em cache na pool. Um token de stake sem contrato consultável
+        // só é aceito em pool agendada, que recebe os saldos de um oráculo antes do início.
+        let (reward_decimals, reward_symbol) = token_metadata(&env, &reward_token).expect("Reward token metadata unavailable");
+        let (stake_decimals, stake_symbol, attested_stake) = match token_metadata(&env, &stake_token) {
+            Some((decimals, symbol)) => (decimals, symbol, false),
+            None => {
+                assert!(status == PoolStatus::Scheduled, "Stake token metadata unavailable");
+                (DEFAULT_TOKEN_DECIMALS, String::from_str(&env, ""), true)
+            }
+        };
+        assert!(
+            stake_decimals <= MAX_TOKEN_DECIMALS && reward_decimals <= MAX_TOKEN_DECIMALS,
+            "Token decimals out of range"
+        );
+
         let pool = Pool {
             id: pool_id,
             owner: owner.clone(),
-            stake_token,
+            stake_token: stake_token.clone(),
             reward_token,
+            stake_decimals,
+            reward_decimals,
+            stake_symbol,
+            reward_symbol,
             total_rewards,
-            max_apy,
+            max_apy: apy_rate_to_bps(apy_rate),
+            apy_rate,
+            next_apy_rate: 0,
+            accrual: AccrualModel::Simple,
+            accrual_rate: accrual_rate(apy_rate, AccrualModel::Simple).raw(),
+            next_apy_at: 0,
+            apy_notice_period: DEFAULT_APY_NOTICE_PERIOD,
             distribution_days,
             daily_distribution,
+            schedule,
+            mode,
+            conversion,
+            streams: Vec::new(&env),
+            epochs_closed: 0,
+            merkle_epoch: 0,
             distributed_amount: 0,
+            escrowed: 0,
+            deposited: 0,
+            sponsored: 0,
+            reserved_rewards: 0,
+            restaked: 0,
+            emitted: 0,
+            last_rate: 0,
+            last_rate_at: 0,
+            reward_per_token: 0,
+            last_update: start_time,
             total_delegated: 0,
-            start_time: current_time,
+            start_time,
             end_time,
-            is_active: true,
+            finalized_at: 0,
+            status,
         };
         
         // Armazenar pool
-        let mut pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        pools.set(pool_id, pool);
-        env.storage().instance().set(&POOLS, &pools);
+        save_pool(&env, &pool);
+        if attested_stake {
+            persistent_set(&env, &DataKey::AttestedStake(pool_id), &true);
+        }
         
         // Atualizar contador
         env.storage().instance().set(&POOL_COUNT, &pool_id);
+
+        // Atualizar índices por dono e por token de stake
+        pool_index_push(&env, PoolIndexKey::Owner(owner), pool_id);
+        pool_index_push(&env, PoolIndexKey::StakeToken(stake_token), pool_id);
         
         pool_id
     }
@@ -120,28 +503,68 @@ impl PoolRewardsContract {
     /// Usuário opta por participar (autoriza visualização/uso do saldo)
     pub fn opt_in(env: Env, user: Address, pool_id: u64) {
         user.require_auth();
-        let pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let _pool = pools.get(pool_id).expect("Pool not found");
+        record_opt_in(&env, &user, pool_id);
+    }
 
-        let mut optins: Map<(Address, u64), bool> = env.storage().instance().get(&OPTIN).unwrap_or(Map::new(&env));
-        optins.set((user.clone(), pool_id), true);
-        env.storage().instance().set(&OPTIN, &optins);
+    /// Opt-in em várias pools de uma só vez
+    pub fn opt_in_many(env: Env, user: Address, pool_ids: Vec<u64>) {
+        user.require_auth();
+        for pool_id in pool_ids.iter() {
+            record_opt_in(&env, &user, pool_id);
+        }
     }
 
     /// Deposita tokens de recompensa na pool (owner -> contrato)
     pub fn deposit_rewards(env: Env, owner: Address, pool_id: u64, amount: i128) {
         owner.require_auth();
         assert!(amount > 0, "Amount must be positive");
-        let mut pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let mut pool = pools.get(pool_id).expect("Pool not found");
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can deposit");
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
 
         let client = TokenClient::new(&env, &pool.reward_token);
         let this = env.current_contract_address();
         client.transfer(&owner, &this, &amount);
 
+        pool = accrue(&env, pool);
+        pool.total_rewards += amount;
+        pool.escrowed += amount;
+        pool.deposited += amount;
+        save_pool(&env, &pool);
+    }
+
+    /// Patrocina uma pool com tokens de recompensa (patrocinador -> contrato).
+    /// A contribuição fica registrada e a parte não usada é devolvida na finalização.
+    pub fn sponsor_pool(env: Env, sponsor: Address, pool_id: u64, amount: i128) {
+        sponsor.require_auth();
+        assert!(amount > 0, "Amount must be positive");
+        let mut pool = load_pool(&env, pool_id);
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+
+        let client = TokenClient::new(&env, &pool.reward_token);
+        client.transfer(&sponsor, &env.current_contract_address(), &amount);
+
+        pool = accrue(&env, pool);
         pool.total_rewards += amount;
-        pools.set(pool_id, pool);
-        env.storage().instance().set(&POOLS, &pools);
+        pool.escrowed += amount;
+        pool.deposited += amount;
+        pool.sponsored += amount;
+
+        let mut sponsors: Map<u64, Map<Address, i128>> = env.storage().instance().get(&SPONSORS).unwrap_or(Map::new(&env));
+        let mut contributions = sponsors.get(pool_id).unwrap_or(Map::new(&env));
+        contributions.set(sponsor.clone(), contributions.get(sponsor.clone()).unwrap_or(0) + amount);
+        sponsors.set(pool_id, contributions);
+        env.storage().instance().set(&SPONSORS, &sponsors);
+
+        // Valores brutos nas casas decimais do token de recompensa
+        env.events().publish((symbol_short!("sponsor"), pool_id), (sponsor, amount, pool.reward_decimals));
+        save_pool(&env, &pool);
+    }
+
+    /// Contribuições de cada patrocinador da pool
+    pub fn get_sponsors(env: Env, pool_id: u64) -> Map<Address, i128> {
+        let sponsors: Map<u64, Map<Address, i128>> = env.storage().instance().get(&SPONSORS).unwrap_or(Map::new(&env));
+        sponsors.get(pool_id).unwrap_or(Map::new(&env))
     }
 
     /// Permite que um usuário delegue tokens para uma pool
@@ -157,68 +580,134 @@ impl PoolRewardsContract {
         assert!(amount > 0, "Delegation amount must be positive");
         
         // Verificar se a pool existe e está ativa
-        let pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let pool = pools.get(pool_id).expect("Pool not found");
-        assert!(pool.is_active, "Pool is not active");
-        
-        let current_time = env.ledger().timestamp();
-        assert!(current_time < pool.end_time, "Pool has ended");
+        let pool = load_pool(&env, pool_id);
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active]);
+        assert!(!uses_attested_balances(&env, pool_id), "Pool uses attested balances");
         
         // Criar ou atualizar delegação
-        let delegation_key = (user.clone(), pool_id);
-        let mut delegations: Map<(Address, u64), Delegation> = 
-            env.storage().instance().get(&DELEGATIONS).unwrap_or(Map::new(&env));
-        
-        let delegation = Delegation {
-            user: user.clone(),
-            pool_id,
-            amount,
-            timestamp: current_time,
-            last_claim: current_time,
-        };
-        
-        delegations.set(delegation_key, delegation);
-        env.storage().instance().set(&DELEGATIONS, &delegations);
+        apply_sync(&env, &user, pool_id, amount);
     }
 
     /// Sincroniza a delegação com o saldo atual do usuário no token de stake
     pub fn sync_delegation(env: Env, user: Address, pool_id: u64) {
         user.require_auth();
-        let mut pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let pool = pools.get(pool_id).expect("Pool not found");
+        let pool = load_pool(&env, pool_id);
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active]);
 
         // Verificar opt-in
-        let optins: Map<(Address, u64), bool> = env.storage().instance().get(&OPTIN).unwrap_or(Map::new(&env));
-        let is_opt_in = optins.get((user.clone(), pool_id)).unwrap_or(false);
-        assert!(is_opt_in, "User not opted-in");
+        assert!(is_opted_in(&env, &user, pool_id), "User not opted-in");
+        assert!(!uses_attested_balances(&env, pool_id), "Pool uses attested balances");
 
         let client = TokenClient::new(&env, &pool.stake_token);
         let balance = client.balance(&user);
 
-        let delegation_key = (user.clone(), pool_id);
-        let mut delegations: Map<(Address, u64), Delegation> = env.storage().instance().get(&DELEGATIONS).unwrap_or(Map::new(&env));
-        let existing = delegations.get(delegation_key.clone());
+        apply_sync(&env, &user, pool_id, balance);
+    }
 
-        let current_time = env.ledger().timestamp();
-        let prev_amount = existing.as_ref().map(|d| d.amount).unwrap_or(0);
-        let delta = balance - prev_amount;
-
-        // Atualiza total delegado
-        let mut pool_mut = pools.get(pool_id).expect("Pool not found");
-        pool_mut.total_delegated += delta;
-        pools.set(pool_id, pool_mut.clone());
-        env.storage().instance().set(&POOLS, &pools);
-
-        // Atualiza delegação
-        let new_delegation = Delegation {
-            user: user.clone(),
-            pool_id,
-            amount: balance,
-            timestamp: current_time,
-            last_claim: existing.as_ref().map(|d| d.last_claim).unwrap_or(current_time),
-        };
-        delegations.set(delegation_key, new_delegation);
-        env.storage().instance().set(&DELEGATIONS, &delegations);
+    /// Sincroniza todas as pools em que o usuário fez opt-in.
+    /// O saldo é lido uma única vez por token de stake. Retorna o número de pools sincronizadas.
+    pub fn sync_all(env: Env, user: Address) -> u32 {
+        user.require_auth();
+        let pool_ids: Vec<u64> = persistent_get(&env, &DataKey::UserPools(user.clone())).unwrap_or(Vec::new(&env));
+
+        // Cache de saldo por token de stake
+        let mut balances: Map<Address, i128> = Map::new(&env);
+        let mut synced = 0u32;
+
+        for pool_id in pool_ids.iter() {
+            // Pools fora de Scheduled/Active e pools com saldos atestados são ignoradas
+            let pool = match stored_pool(&env, pool_id) {
+                Some(p) => p,
+                None => continue,
+            };
+            let status = current_status(&env, &pool);
+            if status != PoolStatus::Scheduled && status != PoolStatus::Active {
+                continue;
+            }
+            if uses_attested_balances(&env, pool_id) {
+                continue;
+            }
+
+            let balance = match balances.get(pool.stake_token.clone()) {
+                Some(b) => b,
+                None => {
+                    let b = TokenClient::new(&env, &pool.stake_token).balance(&user);
+                    balances.set(pool.stake_token.clone(), b);
+                    b
+                }
+            };
+
+            apply_sync(&env, &user, pool_id, balance);
+            synced += 1;
+        }
+        synced
+    }
+
+    /// Define o oráculo que atesta os saldos de stake da pool, para ativos sem contrato de
+    /// token consultável. A partir daí os saldos só mudam via `attest_balances`.
+    /// Só o dono, só uma vez e só antes do início, enquanto não há stake delegado.
+    pub fn set_balance_oracle(env: Env, pool_id: u64, oracle: Address) {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        pool.owner.require_auth();
+        require_status(&env, &pool, &[PoolStatus::Scheduled]);
+        assert!(pool.total_delegated == 0, "Pool already has delegations");
+
+        let mut oracles: Map<u64, Address> = env.storage().instance().get(&BALANCE_ORACLES).unwrap_or(Map::new(&env));
+        assert!(!oracles.contains_key(pool_id), "Balance oracle already set");
+        oracles.set(pool_id, oracle);
+        env.storage().instance().set(&BALANCE_ORACLES, &oracles);
+    }
+
+    /// Aplica saldos atestados pelo oráculo da pool em `as_of`. Atestados antigos ou fora de
+    /// ordem são rejeitados; usuários sem opt-in são ignorados. Retorna quantos foram aplicados.
+    pub fn attest_balances(env: Env, pool_id: u64, balances: Vec<(Address, i128)>, as_of: u64) -> u32 {
+        let oracles: Map<u64, Address> = env.storage().instance().get(&BALANCE_ORACLES).unwrap_or(Map::new(&env));
+        let oracle = oracles.get(pool_id).expect("Balance oracle not set");
+        oracle.require_auth();
+
+        let pool = load_pool(&env, pool_id);
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active]);
+
+        let now = env.ledger().timestamp();
+        assert!(as_of <= now, "Attestation is in the future");
+        assert!(now - as_of <= MAX_ATTESTATION_AGE, "Attestation is stale");
+        let mut attested_at: Map<u64, u64> = env.storage().instance().get(&ATTESTED_AT).unwrap_or(Map::new(&env));
+        if let Some(last) = attested_at.get(pool_id) {
+            assert!(as_of > last, "Attestation is out of order");
+        }
+
+
+        let mut applied = 0u32;
+        for (user, balance) in balances.iter() {
+            assert!(balance >= 0, "Attested balance cannot be negative");
+            if !is_opted_in(&env, &user, pool_id) {
+                continue;
+            }
+            apply_sync(&env, &user, pool_id, balance);
+            applied += 1;
+        }
+
+        attested_at.set(pool_id, as_of);
+        env.storage().instance().set(&ATTESTED_AT, &attested_at);
+        applied
+    }
+
+    /// Retorna o timestamp do último atestado de saldos aplicado na pool
+    pub fn get_last_attestation(env: Env, pool_id: u64) -> Option<u64> {
+        let attested_at: Map<u64, u64> = env.storage().instance().get(&ATTESTED_AT).unwrap_or(Map::new(&env));
+        attested_at.get(pool_id)
+    }
+
+    /// Retorna os IDs das pools em que o usuário fez opt-in (pools canceladas ficam de fora)
+    pub fn get_user_pools(env: Env, user: Address) -> Vec<u64> {
+        let pool_ids: Vec<u64> = persistent_get(&env, &DataKey::UserPools(user)).unwrap_or(Vec::new(&env));
+        let mut result = Vec::new(&env);
+        for pool_id in pool_ids.iter() {
+            if !is_cancelled(&env, pool_id) {
+                result.push_back(pool_id);
+            }
+        }
+        result
     }
 
     /// Calcula as recompensas pendentes para um usuário em uma pool
@@ -227,93 +716,311 @@ impl PoolRewardsContract {
         user: Address,
         pool_id: u64,
     ) -> i128 {
-        let pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let pool = pools.get(pool_id).expect("Pool not found");
+        let pool = load_pool(&env, pool_id);
         
-        let delegations: Map<(Address, u64), Delegation> = 
-            env.storage().instance().get(&DELEGATIONS).unwrap_or(Map::new(&env));
         
-        let delegation_key = (user, pool_id);
-        let delegation = match delegations.get(delegation_key) {
+        let delegation = match load_delegation(&env, &user, pool_id) {
             Some(d) => d,
             None => return 0,
         };
         
-        let current_time = env.ledger().timestamp();
-        let time_since_last_claim = current_time - delegation.last_claim;
-        if time_since_last_claim == 0 {
-            return 0;
-        }
-        
-        // Calcular recompensas proporcionais ao tempo (por segundo)
-        // annual_reward = amount * apy_bps / 10000
-        // pending = annual_reward * elapsed_seconds / (365 * 86400)
-        let annual_reward = (delegation.amount * pool.max_apy as i128) / 10000;
-        let pending_rewards = (annual_reward * (time_since_last_claim as i128)) / (365i128 * 86400i128);
-        
-        // Limitar às recompensas disponíveis na pool
-        let remaining_rewards = pool.total_rewards - pool.distributed_amount;
-        if pending_rewards > remaining_rewards {
-            remaining_rewards
-        } else {
-            pending_rewards
-        }
+        pending_rewards(&env, &pool, &delegation)
     }
 
-    /// Permite que um usuário reivindique suas recompensas
+    /// Permite que um usuário reivindique suas recompensas de todos os fluxos da pool.
+    /// A taxa do protocolo é descontada de cada token. Retorna o valor líquido pago por token.
     pub fn claim_rewards(
         env: Env,
         user: Address,
         pool_id: u64,
-    ) -> i128 {
+    ) -> Map<Address, i128> {
         user.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        let status = current_status(&env, &pool);
+        assert!(status != PoolStatus::Cancelled, "Pool is cancelled");
         
-        let pending_rewards = Self::calculate_pending_rewards(env.clone(), user.clone(), pool_id);
-        if pending_rewards <= 0 {
-            return 0;
+        let mut payouts: Map<Address, i128> = Map::new(&env);
+        let mut delegation = match load_delegation(&env, &user, pool_id) {
+            Some(d) => d,
+            None => return payouts,
+        };
+
+        // Checkpoint do acumulador e da delegação
+        pool = accrue(&env, pool);
+        settle(&pool, &mut delegation);
+
+        // Fluxo principal, limitado ao que a pool tem custodiado. Depois da finalização o
+        // acumulado já está reservado; antes, a reserva pertence a outros. O que faltar
+        // continua acumulado até novos depósitos.
+        let available = if status == PoolStatus::Finalized {
+            pool.escrowed
+        } else {
+            pool.escrowed - pool.reserved_rewards
+        };
+        let pending_rewards = delegation.accrued.min(available.max(0));
+        if pending_rewards > 0 {
+            if status == PoolStatus::Finalized {
+                pool.reserved_rewards -= pending_rewards;
+            }
+            pool.distributed_amount += pending_rewards;
+            pool.escrowed -= pending_rewards;
+            delegation.accrued -= pending_rewards;
+            add_payout(&mut payouts, &pool.reward_token, pending_rewards);
         }
-        
-        // Atualizar delegação
-        let mut delegations: Map<(Address, u64), Delegation> = 
-            env.storage().instance().get(&DELEGATIONS).unwrap_or(Map::new(&env));
-        
-        let delegation_key = (user.clone(), pool_id);
-        let mut delegation = delegations.get(delegation_key.clone()).expect("Delegation not found");
+
+        // Fluxos adicionais
+        let mut streams = pool.streams.clone();
+        let mut checkpoints = delegation.streams.clone();
+        for i in 0..streams.len() {
+            let mut checkpoint = checkpoints.get_unchecked(i);
+            if checkpoint.accrued <= 0 {
+                continue;
+            }
+            let mut stream = streams.get_unchecked(i);
+            stream.distributed += checkpoint.accrued;
+            stream.escrowed -= checkpoint.accrued;
+            add_payout(&mut payouts, &stream.token, checkpoint.accrued);
+            checkpoint.accrued = 0;
+            streams.set(i, stream);
+            checkpoints.set(i, checkpoint);
+        }
+        if payouts.is_empty() {
+            return payouts;
+        }
+        pool.streams = streams;
+        delegation.streams = checkpoints;
+
+        // Atualizar delegação e pool
         delegation.last_claim = env.ledger().timestamp();
-        delegations.set(delegation_key, delegation);
-        env.storage().instance().set(&DELEGATIONS, &delegations);
-        
-        // Atualizar pool
-        let mut pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let mut pool = pools.get(pool_id).expect("Pool not found");
-        pool.distributed_amount += pending_rewards;
-        pools.set(pool_id, pool.clone());
-        env.storage().instance().set(&POOLS, &pools);
+        save_delegation(&env, &delegation);
+        save_pool(&env, &pool);
 
-        // Efetua pagamento onchain da recompensa (contrato -> usuário)
-        let client = TokenClient::new(&env, &pool.reward_token);
+        // Separa a taxa do protocolo de cada token
+        let mut net_payouts: Map<Address, i128> = Map::new(&env);
+        for (token, amount) in payouts.iter() {
+            net_payouts.set(token.clone(), take_protocol_fee(&env, &token, amount));
+        }
+
+        // Efetua pagamento onchain das recompensas (contrato -> usuário)
         let this = env.current_contract_address();
-        client.transfer(&this, &user, &pending_rewards);
+        for (token, amount) in net_payouts.iter() {
+            if amount > 0 {
+                TokenClient::new(&env, &token).transfer(&this, &user, &amount);
+            }
+        }
+
+        net_payouts
+    }
+
+    /// Recompensas pendentes do usuário em todos os fluxos da pool, por token
+    pub fn get_pending_rewards_by_token(env: Env, user: Address, pool_id: u64) -> Map<Address, i128> {
+        let pool = accrue(&env, load_pool(&env, pool_id));
+
+        let mut pending: Map<Address, i128> = Map::new(&env);
+        let mut delegation = match load_delegation(&env, &user, pool_id) {
+            Some(d) => d,
+            None => return pending,
+        };
+        settle(&pool, &mut delegation);
+
+        add_payout(&mut pending, &pool.reward_token, delegation.accrued);
+        for (stream, checkpoint) in pool.streams.iter().zip(delegation.streams.iter()) {
+            add_payout(&mut pending, &stream.token, checkpoint.accrued);
+        }
+        pending
+    }
 
-        pending_rewards
+    /// Adiciona um fluxo de recompensa à pool, com token, orçamento e curva próprios.
+    /// O orçamento é transferido do dono e distribuído até o fim atual da pool.
+    /// Retorna o índice do fluxo.
+    pub fn add_reward_stream(
+        env: Env,
+        owner: Address,
+        pool_id: u64,
+        token: Address,
+        amount: i128,
+        schedule: EmissionSchedule,
+    ) -> u32 {
+        owner.require_auth();
+        assert!(amount > 0, "Amount must be positive");
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can add streams");
+        assert!(pool.mode == DistributionMode::Continuous, "Reward streams require continuous mode");
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+        validate_schedule(&schedule, amount);
+
+        pool = accrue(&env, pool);
+        let start_time = env.ledger().timestamp().max(pool.start_time);
+        let (decimals, _) = token_metadata(&env, &token).expect("Stream token metadata unavailable");
+        assert!(decimals <= MAX_TOKEN_DECIMALS, "Token decimals out of range");
+        let stream = RewardStream {
+            token: token.clone(),
+            decimals,
+            total_rewards: amount,
+            daily_distribution: mul_div_floor(amount, 86400, (pool.end_time - start_time) as i128),
+            schedule,
+            escrowed: amount,
+            emitted: 0,
+            distributed: 0,
+            reward_per_token: 0,
+            last_update: start_time,
+            start_time,
+            end_time: pool.end_time,
+            ended: false,
+        };
+        pool.streams.push_back(stream);
+        let index = pool.streams.len() - 1;
+        save_pool(&env, &pool);
+
+        TokenClient::new(&env, &token).transfer(&owner, &env.current_contract_address(), &amount);
+        index
+    }
+
+    /// Aumenta o orçamento de um fluxo. Em fluxos de emissão constante, a nova taxa é o
+    /// orçamento restante dividido pelo tempo restante.
+    pub fn top_up_stream(env: Env, owner: Address, pool_id: u64, stream_index: u32, amount: i128) {
+        owner.require_auth();
+        assert!(amount > 0, "Amount must be positive");
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can top up streams");
+        pool = accrue(&env, pool);
+
+        let mut stream = pool.streams.get(stream_index).expect("Stream not found");
+        assert!(!stream.ended, "Stream has ended");
+        let from = env.ledger().timestamp().max(stream.start_time);
+        assert!(from < stream.end_time, "Stream has ended");
+
+        stream.total_rewards += amount;
+        stream.escrowed += amount;
+        if stream.schedule == EmissionSchedule::Constant {
+            stream.daily_distribution = mul_div_floor(stream.total_rewards - stream.emitted, 86400, (stream.end_time - from) as i128);
+        }
+        let token = stream.token.clone();
+        pool.streams.set(stream_index, stream);
+        save_pool(&env, &pool);
+
+        TokenClient::new(&env, &token).transfer(&owner, &env.current_contract_address(), &amount);
+    }
+
+    /// Encerra um fluxo agora e devolve ao dono o orçamento ainda não atribuído.
+    /// O que já foi acumulado continua resgatável pelos delegadores.
+    pub fn end_stream(env: Env, owner: Address, pool_id: u64, stream_index: u32) -> i128 {
+        owner.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can end streams");
+        pool = accrue(&env, pool);
+
+        let mut stream = pool.streams.get(stream_index).expect("Stream not found");
+        assert!(!stream.ended, "Stream has ended");
+        let now = env.ledger().timestamp().clamp(stream.start_time, stream.end_time);
+        stream.end_time = now;
+        stream.last_update = now;
+        stream.ended = true;
+
+        // Devolve o que não foi atribuído a ninguém
+        let refund = stream.escrowed - (stream.emitted - stream.distributed);
+        stream.escrowed -= refund;
+        let token = stream.token.clone();
+        pool.streams.set(stream_index, stream);
+        save_pool(&env, &pool);
+
+        if refund > 0 {
+            TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &owner, &refund);
+        }
+        refund
     }
 
-    /// Retorna informações de uma pool
+    /// Retorna informações de uma pool (com status e acumulador calculados no momento da consulta)
     pub fn get_pool(env: Env, pool_id: u64) -> Pool {
-        let pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        pools.get(pool_id).expect("Pool not found")
+        pool_view(&env, load_pool(&env, pool_id))
+    }
+
+    /// Reinveste as recompensas pendentes como stake adicional, sem transferência de tokens.
+    /// Só em pools em que o token de stake e o de recompensa são o mesmo.
+    pub fn compound(env: Env, user: Address, pool_id: u64) -> i128 {
+        user.require_auth();
+        compound_delegation(&env, &user, pool_id)
+    }
+
+    /// Liga ou desliga o reinvestimento automático, que qualquer keeper pode disparar
+    pub fn set_auto_compound(env: Env, user: Address, pool_id: u64, enabled: bool) {
+        user.require_auth();
+        let pool = Self::get_pool(env.clone(), pool_id);
+        assert!(pool.stake_token == pool.reward_token, "Compounding requires the same stake and reward token");
+
+        let mut auto: Map<(Address, u64), bool> = env.storage().instance().get(&AUTO_COMPOUND).unwrap_or(Map::new(&env));
+        if enabled {
+            auto.set((user, pool_id), true);
+        } else {
+            auto.remove((user, pool_id));
+        }
+        env.storage().instance().set(&AUTO_COMPOUND, &auto);
+    }
+
+    /// Reinveste as recompensas dos usuários com reinvestimento automático ligado.
+    /// Pode ser chamada por qualquer conta; usuários sem a opção são ignorados.
+    /// Retorna o total reinvestido.
+    pub fn auto_compound(env: Env, pool_id: u64, users: Vec<Address>) -> i128 {
+        let auto: Map<(Address, u64), bool> = env.storage().instance().get(&AUTO_COMPOUND).unwrap_or(Map::new(&env));
+
+        let mut total = 0i128;
+        for user in users.iter() {
+            if !auto.get((user.clone(), pool_id)).unwrap_or(false) {
+                continue;
+            }
+            total += compound_delegation(&env, &user, pool_id);
+        }
+        total
+    }
+
+    /// Saca o stake reinvestido, encerrando seu rendimento. Retorna o valor pago.
+    pub fn withdraw_compounded(env: Env, user: Address, pool_id: u64) -> i128 {
+        user.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        let mut delegation = match load_delegation(&env, &user, pool_id) {
+            Some(d) => d,
+            None => return 0,
+        };
+
+        // Checkpoint antes de reduzir o stake
+        pool = accrue(&env, pool);
+        settle(&pool, &mut delegation);
+        let amount = delegation.compounded;
+        if amount <= 0 {
+            return 0;
+        }
+
+        delegation.compounded = 0;
+
+        pool.total_delegated -= amount;
+        pool.restaked -= amount;
+        if pool.mode == DistributionMode::EpochSnapshot {
+            record_epoch_snapshot(&env, &pool, &user, delegation.amount);
+        }
+        save_pool(&env, &pool);
+
+        save_delegation(&env, &delegation);
+
+        let client = TokenClient::new(&env, &pool.reward_token);
+        client.transfer(&env.current_contract_address(), &user, &amount);
+        amount
     }
 
     /// Retorna todas as pools ativas
     pub fn get_active_pools(env: Env) -> Vec<Pool> {
-        let pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
         let mut active_pools = Vec::new(&env);
         
         let pool_count: u64 = env.storage().instance().get(&POOL_COUNT).unwrap_or(0);
         
         for i in 1..=pool_count {
-            if let Some(pool) = pools.get(i) {
-                if pool.is_active {
+            if let Some(pool) = stored_pool(&env, i) {
+                let pool = pool_view(&env, pool);
+                if pool.status == PoolStatus::Active {
                     active_pools.push_back(pool);
                 }
             }
@@ -322,114 +1029,2741 @@ impl PoolRewardsContract {
         active_pools
     }
 
-    /// Permite ao dono da pool pausar/despausar a pool
-    pub fn toggle_pool_status(
-        env: Env,
-        owner: Address,
-        pool_id: u64,
-    ) {
-        owner.require_auth();
-        
-        let mut pools: Map<u64, Pool> = env.storage().instance().get(&POOLS).unwrap_or(Map::new(&env));
-        let mut pool = pools.get(pool_id).expect("Pool not found");
+    /// Lista pools de forma paginada e filtrada, percorrendo no máximo `MAX_POOLS_SCANNED` pools por chamada.
+    /// `cursor` é o último ID já percorrido (0 para começar). Retorna a página e o próximo cursor,
+    /// que é 0 quando não há mais pools; uma página vazia com cursor diferente de 0 só indica que a busca continua.
+    pub fn list_pools(env: Env, cursor: u64, limit: u32, filters: Vec<PoolFilter>) -> (Vec<Pool>, u64) {
+        let limit = limit.min(MAX_PAGE_SIZE);
+        let mut result = Vec::new(&env);
+        let mut next_cursor = 0u64;
+
+        // Usa o índice mais seletivo disponível; sem índice, percorre os IDs em ordem.
+        // Pools e entradas de índice ficam em chaves próprias: a página só lê o que percorre.
+        let mut index: Option<PoolIndexKey> = None;
+        for filter in filters.iter() {
+            match filter {
+                PoolFilter::Owner(owner) => {
+                    index = Some(PoolIndexKey::Owner(owner));
+                    break;
+                }
+                PoolFilter::StakeToken(stake_token) => {
+                    index = Some(PoolIndexKey::StakeToken(stake_token));
+                }
+                _ => {}
+            }
+        }
+
+        match index {
+            Some(index) => {
+                let len: u32 = persistent_get(&env, &DataKey::PoolIndexLen(index.clone())).unwrap_or(0);
+                // Busca binária pela primeira entrada depois do cursor (IDs crescem com a criação)
+                let (mut start, mut end) = (0u32, len);
+                while start < end {
+                    let mid = start + (end - start) / 2;
+                    if pool_index_get(&env, &index, mid) <= cursor {
+                        start = mid + 1;
+                    } else {
+                        end = mid;
+                    }
+                }
+                let mut position = start;
+                while position < len && position - start < MAX_POOLS_SCANNED && result.len() < limit {
+                    let pool_id = pool_index_get(&env, &index, position);
+                    let pool = pool_view(&env, load_pool(&env, pool_id));
+                    if pool_matches(&pool, &filters) {
+                        result.push_back(pool);
+                    }
+                    next_cursor = pool_id;
+                    position += 1;
+                }
+                if position >= len {
+                    next_cursor = 0;
+                }
+            }
+            None => {
+                let pool_count: u64 = env.storage().instance().get(&POOL_COUNT).unwrap_or(0);
+                let mut pool_id = cursor.saturating_add(1);
+                let mut scanned = 0u32;
+                while pool_id <= pool_count && scanned < MAX_POOLS_SCANNED && result.len() < limit {
+                    if let Some(pool) = stored_pool(&env, pool_id) {
+                        let pool = pool_view(&env, pool);
+                        if pool_matches(&pool, &filters) {
+                            result.push_back(pool);
+                        }
+                    }
+                    next_cursor = pool_id;
+                    pool_id += 1;
+                    scanned += 1;
+                }
+                if pool_id > pool_count {
+                    next_cursor = 0;
+                }
+            }
+        }
+
+        (result, next_cursor)
+    }
+
+    /// Permite ao dono da pool pausar/despausar a pool (somente entre Active e Paused)
+    pub fn toggle_pool_status(
+        env: Env,
+        owner: Address,
+        pool_id: u64,
+    ) {
+        owner.require_auth();
+        
+        let mut pool = load_pool(&env, pool_id);
         
         assert!(pool.owner == owner, "Only pool owner can toggle status");
         
-        pool.is_active = !pool.is_active;
-        pools.set(pool_id, pool);
-        env.storage().instance().set(&POOLS, &pools);
+        let next = match current_status(&env, &pool) {
+            PoolStatus::Active => PoolStatus::Paused,
+            PoolStatus::Paused => PoolStatus::Active,
+            _ => panic!("Pool status cannot be toggled"),
+        };
+        // Checkpoint antes da troca: o período pausado não acumula recompensas
+        pool = accrue(&env, pool);
+        set_status(&env, &mut pool, next);
+        save_pool(&env, &pool);
+    }
+
+    /// Cancela uma pool antes do início ou antes de qualquer recompensa acumulada.
+    /// Devolve as recompensas custodiadas ao dono. Opt-ins e delegações deixam de valer e a
+    /// pool sai das consultas por usuário, sem percorrer os participantes.
+    pub fn cancel_pool(env: Env, owner: Address, pool_id: u64) -> i128 {
+        owner.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can cancel");
+
+
+        // Depois do início, só é possível cancelar se nada foi acumulado. Nos modos cumulativos
+        // os direitos não passam pelo acumulador: uma raiz publicada ou um voucher assinado
+        // já comprometem o custodiado, e vouchers só são conhecidos no resgate.
+        if current_status(&env, &pool) != PoolStatus::Scheduled {
+            assert!(pool.mode != DistributionMode::Voucher, "Voucher pools can only be cancelled before start");
+            assert!(pool.merkle_epoch == 0, "Merkle root already posted");
+            pool = accrue(&env, pool);
+            assert!(pool.emitted == 0 && pool.distributed_amount == 0, "Rewards already accrued");
+            for stream in pool.streams.iter() {
+                assert!(stream.emitted == 0, "Rewards already accrued");
+            }
+        }
+
+        set_status(&env, &mut pool, PoolStatus::Cancelled);
+        pool.total_delegated = 0;
+
+        // Devolve as recompensas custodiadas: patrocinadores recebem sua parte, o dono o restante
+        let escrowed = pool.escrowed;
+        refund_sponsors(&env, pool_id, &mut pool, escrowed);
+        let refund = pool.escrowed;
+        pool.escrowed = 0;
+        save_pool(&env, &pool);
+
+        if refund > 0 {
+            let client = TokenClient::new(&env, &pool.reward_token);
+            client.transfer(&env.current_contract_address(), &owner, &refund);
+        }
+
+        // Fluxos adicionais são devolvidos integralmente, no token de cada um
+        let mut streams = pool.streams.clone();
+        for i in 0..streams.len() {
+            let mut stream = streams.get_unchecked(i);
+            if stream.escrowed > 0 {
+                TokenClient::new(&env, &stream.token).transfer(&env.current_contract_address(), &owner, &stream.escrowed);
+            }
+            stream.escrowed = 0;
+            stream.ended = true;
+            streams.set(i, stream);
+        }
+        pool.streams = streams;
+        save_pool(&env, &pool);
+
+        refund
+    }
+
+    /// Estende a emissão de uma pool em andamento: deposita `extra_amount`, adiciona `extra_days`
+    /// e recalcula a distribuição diária para o restante da vida da pool. O acúmulo até agora
+    /// é liquidado com a taxa anterior.
+    pub fn extend_pool(env: Env, owner: Address, pool_id: u64, extra_amount: i128, extra_days: u32) {
+        owner.require_auth();
+        assert!(extra_amount >= 0, "Amount must not be negative");
+        assert!(extra_amount > 0 || extra_days > 0, "Nothing to extend");
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can extend");
+        assert!(pool.schedule == EmissionSchedule::Constant, "Only constant schedules can be extended");
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+
+        // Liquida o acúmulo com a taxa atual
+        pool = accrue(&env, pool);
+
+        if extra_amount > 0 {
+            let client = TokenClient::new(&env, &pool.reward_token);
+            client.transfer(&owner, &env.current_contract_address(), &extra_amount);
+            pool.total_rewards += extra_amount;
+            pool.escrowed += extra_amount;
+            pool.deposited += extra_amount;
+        }
+
+        pool.distribution_days += extra_days;
+        pool.end_time += extra_days as u64 * 86400;
+
+        // Nova taxa: orçamento restante dividido pelo tempo restante
+        let from = env.ledger().timestamp().max(pool.start_time);
+        let remaining_seconds = (pool.end_time - from) as i128;
+        pool.daily_distribution = mul_div_floor(pool.total_rewards - pool.emitted, 86400, remaining_seconds);
+
+        save_pool(&env, &pool);
+    }
+
+    /// Prévia da emissão da curva da pool no intervalo [from, to) (antes do limite de APY)
+    pub fn preview_emission(env: Env, pool_id: u64, from: u64, to: u64) -> i128 {
+        assert!(from <= to, "Invalid interval");
+        let pool = Self::get_pool(env, pool_id);
+        cumulative_emission(&pool, to) - cumulative_emission(&pool, from)
+    }
+
+    /// Retorna o registro de uma época (modo EpochSnapshot). Épocas sem sync herdam o
+    /// último registro anterior, tanto do total da pool quanto do saldo do usuário.
+    pub fn get_epoch_snapshot(env: Env, pool_id: u64, epoch: u32, user: Address) -> EpochSnapshot {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        assert!(pool.mode == DistributionMode::EpochSnapshot, "Pool is not in epoch mode");
+
+        let total_delegated = epoch_value_at(&env, &DataKey::LastEpochTotal(pool_id), |e| DataKey::EpochTotal(pool_id, e), epoch);
+        let user_balance = epoch_value_at(
+            &env,
+            &DataKey::LastEpochBalance(user.clone(), pool_id),
+            |e| DataKey::EpochBalance(user.clone(), pool_id, e),
+            epoch,
+        );
+
+        let epoch_start = pool.start_time + epoch as u64 * EPOCH_LENGTH;
+        EpochSnapshot {
+            epoch,
+            total_delegated,
+            user_balance,
+            emission: cumulative_emission(&pool, epoch_start + EPOCH_LENGTH) - cumulative_emission(&pool, epoch_start),
+            closed: epoch < pool.epochs_closed,
+        }
+    }
+
+    /// Define o distribuidor autorizado a publicar raízes Merkle da pool
+    pub fn set_distributor(env: Env, pool_id: u64, distributor: Address) {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        pool.owner.require_auth();
+        assert!(pool.mode == DistributionMode::Merkle, "Pool is not in merkle mode");
+
+        let mut distributors: Map<u64, Address> = env.storage().instance().get(&DISTRIBUTORS).unwrap_or(Map::new(&env));
+        distributors.set(pool_id, distributor);
+        env.storage().instance().set(&DISTRIBUTORS, &distributors);
+    }
+
+    /// Publica a raiz Merkle dos direitos acumulados (usuário, valor total) até a época.
+    /// Somente o dono ou o distribuidor; as épocas devem ser crescentes.
+    pub fn post_merkle_root(env: Env, caller: Address, pool_id: u64, epoch: u32, root: BytesN<32>) {
+        caller.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.mode == DistributionMode::Merkle, "Pool is not in merkle mode");
+        let distributors: Map<u64, Address> = env.storage().instance().get(&DISTRIBUTORS).unwrap_or(Map::new(&env));
+        assert!(
+            caller == pool.owner || distributors.get(pool_id) == Some(caller.clone()),
+            "Only owner or distributor can post roots"
+        );
+        require_status(&env, &pool, &[PoolStatus::Active, PoolStatus::Paused, PoolStatus::Ended]);
+        assert!(epoch > pool.merkle_epoch, "Epoch must be greater than the last posted");
+
+        let mut roots: Map<(u64, u32), BytesN<32>> = env.storage().instance().get(&MERKLE_ROOTS).unwrap_or(Map::new(&env));
+        roots.set((pool_id, epoch), root);
+        env.storage().instance().set(&MERKLE_ROOTS, &roots);
+
+        pool.merkle_epoch = epoch;
+        save_pool(&env, &pool);
+    }
+
+    /// Resgata recompensas comprovadas pela raiz Merkle mais recente. `cumulative_amount` é o
+    /// direito total do usuário; é pago apenas o que excede o já resgatado.
+    pub fn claim_merkle(
+        env: Env,
+        user: Address,
+        pool_id: u64,
+        cumulative_amount: i128,
+        proof: Vec<BytesN<32>>,
+    ) -> i128 {
+        user.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.mode == DistributionMode::Merkle, "Pool is not in merkle mode");
+        assert!(current_status(&env, &pool) != PoolStatus::Cancelled, "Pool is cancelled");
+
+        let roots: Map<(u64, u32), BytesN<32>> = env.storage().instance().get(&MERKLE_ROOTS).unwrap_or(Map::new(&env));
+        let root = roots.get((pool_id, pool.merkle_epoch)).expect("No merkle root posted");
+        let leaf = merkle_leaf(&env, pool_id, &user, cumulative_amount);
+        assert!(verify_merkle_proof(&env, leaf, &proof, &root), "Invalid merkle proof");
+
+        pay_cumulative(&env, &mut pool, &MERKLE_CLAIMED, &user, cumulative_amount)
+    }
+
+    /// Retorna a raiz Merkle mais recente da pool, se houver
+    pub fn get_merkle_root(env: Env, pool_id: u64) -> Option<BytesN<32>> {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let roots: Map<(u64, u32), BytesN<32>> = env.storage().instance().get(&MERKLE_ROOTS).unwrap_or(Map::new(&env));
+        roots.get((pool_id, pool.merkle_epoch))
+    }
+
+    /// Retorna o total já resgatado via provas Merkle por um usuário
+    pub fn get_merkle_claimed(env: Env, user: Address, pool_id: u64) -> i128 {
+        let claimed: Map<(Address, u64), i128> = env.storage().instance().get(&MERKLE_CLAIMED).unwrap_or(Map::new(&env));
+        claimed.get((user, pool_id)).unwrap_or(0)
+    }
+
+    /// Registra a chave pública ed25519 que assina os vouchers da pool. Só o dono, e só uma vez;
+    /// trocas posteriores passam por `rotate_voucher_signer`.
+    pub fn set_voucher_signer(env: Env, pool_id: u64, public_key: BytesN<32>) {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        pool.owner.require_auth();
+        assert!(pool.mode == DistributionMode::Voucher, "Pool is not in voucher mode");
+
+        let mut signers: Map<u64, BytesN<32>> = env.storage().instance().get(&VOUCHER_SIGNERS).unwrap_or(Map::new(&env));
+        assert!(!signers.contains_key(pool_id), "Voucher signer already set");
+        signers.set(pool_id, public_key);
+        env.storage().instance().set(&VOUCHER_SIGNERS, &signers);
+    }
+
+    /// Troca a chave de assinatura de vouchers da pool (somente administrador)
+    pub fn rotate_voucher_signer(env: Env, admin: Address, pool_id: u64, public_key: BytesN<32>) {
+        admin.require_auth();
+        let stored_admin: Address = env.storage().instance().get(&ADMIN).expect("Admin not set");
+        assert!(admin == stored_admin, "Only admin can rotate voucher signer");
+
+        let mut signers: Map<u64, BytesN<32>> = env.storage().instance().get(&VOUCHER_SIGNERS).unwrap_or(Map::new(&env));
+        assert!(signers.contains_key(pool_id), "Voucher signer not set");
+        signers.set(pool_id, public_key);
+        env.storage().instance().set(&VOUCHER_SIGNERS, &signers);
+    }
+
+    /// Resgata recompensas com um voucher assinado `(pool_id, user, cumulative_amount, nonce, expiry)`.
+    /// O nonce deve ser maior que o último usado pelo usuário na pool.
+    pub fn claim_with_voucher(
+        env: Env,
+        user: Address,
+        pool_id: u64,
+        cumulative_amount: i128,
+        nonce: u64,
+        expiry: u64,
+        signature: BytesN<64>,
+    ) -> i128 {
+        user.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.mode == DistributionMode::Voucher, "Pool is not in voucher mode");
+        assert!(current_status(&env, &pool) != PoolStatus::Cancelled, "Pool is cancelled");
+        assert!(env.ledger().timestamp() <= expiry, "Voucher expired");
+
+        let mut nonces: Map<(Address, u64), u64> = env.storage().instance().get(&VOUCHER_NONCES).unwrap_or(Map::new(&env));
+        if let Some(last) = nonces.get((user.clone(), pool_id)) {
+            assert!(nonce > last, "Voucher nonce already used");
+        }
+
+        // ed25519_verify aborta a execução se a assinatura for inválida
+        let signers: Map<u64, BytesN<32>> = env.storage().instance().get(&VOUCHER_SIGNERS).unwrap_or(Map::new(&env));
+        let public_key = signers.get(pool_id).expect("Voucher signer not set");
+        let message = voucher_message(&env, pool_id, &user, cumulative_amount, nonce, expiry);
+        env.crypto().ed25519_verify(&public_key, &message, &signature);
+
+        nonces.set((user.clone(), pool_id), nonce);
+        env.storage().instance().set(&VOUCHER_NONCES, &nonces);
+
+        pay_cumulative(&env, &mut pool, &VOUCHER_CLAIMED, &user, cumulative_amount)
+    }
+
+    /// Retorna o último nonce de voucher usado por um usuário na pool
+    pub fn get_voucher_nonce(env: Env, user: Address, pool_id: u64) -> Option<u64> {
+        let nonces: Map<(Address, u64), u64> = env.storage().instance().get(&VOUCHER_NONCES).unwrap_or(Map::new(&env));
+        nonces.get((user, pool_id))
+    }
+
+    /// Retorna os preços USD do stake e da recompensa e as casas decimais do oráculo SEP-40
+    /// de uma pool `UsdPrice`
+    pub fn get_usd_prices(env: Env, pool_id: u64) -> (i128, i128, u32) {
+        let pool = Self::get_pool(env.clone(), pool_id);
+        let oracle = match pool.conversion {
+            ConversionRate::UsdPrice(oracle) => PriceOracleClient::new(&env, &oracle),
+            _ => panic!("Pool is not USD-denominated"),
+        };
+        let stake_price = usd_price(&env, &oracle, &pool.stake_token).expect("Oracle price unavailable");
+        let reward_price = usd_price(&env, &oracle, &pool.reward_token).expect("Oracle price unavailable");
+        (stake_price, reward_price, oracle.decimals())
+    }
+
+    /// Propõe uma mudança no APY máximo da pool, em pontos base (compatibilidade).
+    /// Equivale a `propose_apy_rate_change` com `new_apy * 100_000`.
+    pub fn propose_apy_change(env: Env, pool_id: u64, new_apy: u32) -> u64 {
+        assert!(new_apy > 0 && new_apy <= 10000, "APY must be between 0.01% and 100%");
+        Self::propose_apy_rate_change(env, pool_id, bps_to_apy_rate(new_apy))
+    }
+
+    /// Propõe uma mudança no APY máximo da pool, em escala 1e9. A mudança só entra em vigor
+    /// após o aviso prévio configurado na pool; uma nova proposta substitui a anterior.
+    pub fn propose_apy_rate_change(env: Env, pool_id: u64, new_apy_rate: i128) -> u64 {
+        let mut pool = load_pool(&env, pool_id);
+        pool.owner.require_auth();
+
+        assert!(new_apy_rate > 0 && new_apy_rate <= APY_RATE_SCALE, "APY must be positive and at most 100%");
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+
+        // Aplica uma eventual mudança já vencida antes de registrar a nova
+        pool = accrue(&env, pool);
+        let effective_at = env.ledger().timestamp() + pool.apy_notice_period;
+        pool.next_apy_rate = new_apy_rate;
+        pool.next_apy_at = effective_at;
+
+        save_pool(&env, &pool);
+        effective_at
+    }
+
+    /// Retorna a mudança de APY pendente, se houver
+    pub fn get_pending_apy_change(env: Env, pool_id: u64) -> Option<ApyChange> {
+        let pool = Self::get_pool(env, pool_id);
+        if pool.next_apy_at == 0 {
+            return None;
+        }
+        Some(ApyChange {
+            new_apy: apy_rate_to_bps(pool.next_apy_rate),
+            new_apy_rate: pool.next_apy_rate,
+            effective_at: pool.next_apy_at,
+        })
+    }
+
+    /// Ajusta o aviso prévio de mudanças de APY. Só pode ser reduzido antes do início da pool.
+    pub fn set_apy_notice_period(env: Env, pool_id: u64, notice_period: u64) {
+        let mut pool = load_pool(&env, pool_id);
+        pool.owner.require_auth();
+
+        let status = current_status(&env, &pool);
+        assert!(
+            status == PoolStatus::Scheduled || notice_period >= pool.apy_notice_period,
+            "Notice period can only be increased after start"
+        );
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+
+        pool.apy_notice_period = notice_period;
+        save_pool(&env, &pool);
+    }
+
+    /// Escolhe entre juros simples e compostos. Só é permitido antes de qualquer acúmulo.
+    pub fn set_accrual_model(env: Env, pool_id: u64, model: AccrualModel) {
+        let mut pool = load_pool(&env, pool_id);
+        pool.owner.require_auth();
+
+        pool = accrue(&env, pool);
+        assert!(pool.reward_per_token == 0, "Accrual model can only change before rewards accrue");
+        require_status(&env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active, PoolStatus::Paused]);
+
+        pool.accrual = model;
+        pool.accrual_rate = accrual_rate(pool.apy_rate, model).raw();
+        save_pool(&env, &pool);
+    }
+
+    /// Finaliza uma pool encerrada: contabiliza o acúmulo final e reserva o total ainda
+    /// devido aos delegadores. Cada delegador liquida sua parte ao resgatar, então a
+    /// finalização não percorre as delegações. Pode ser chamada por qualquer conta.
+    pub fn finalize_pool(env: Env, pool_id: u64) -> i128 {
+        let mut pool = load_pool(&env, pool_id);
+        pool = accrue(&env, pool);
+        set_status(&env, &mut pool, PoolStatus::Finalized);
+
+        // Acumulado e ainda não pago, nunca além do que está custodiado
+        pool.reserved_rewards = (pool.emitted - pool.distributed_amount).clamp(0, pool.escrowed.max(0));
+
+        // Nos modos cumulativos, provas e vouchers ainda não resgatados podem cobrir todo o
+        // custodiado: tudo fica reservado até o fim da janela de resgate
+        if uses_cumulative_claims(&pool) {
+            pool.reserved_rewards = pool.escrowed;
+        }
+
+        // Patrocinadores recebem de volta sua parte das sobras; o restante fica com o dono
+        let unused = pool.escrowed - pool.reserved_rewards;
+        refund_sponsors(&env, pool_id, &mut pool, unused);
+
+        pool.finalized_at = env.ledger().timestamp();
+        save_pool(&env, &pool);
+
+        pool.reserved_rewards
+    }
+
+    /// Permite ao dono retirar as recompensas não distribuídas após a carência da finalização
+    pub fn withdraw_unused_rewards(env: Env, owner: Address, pool_id: u64) -> i128 {
+        owner.require_auth();
+
+        let mut pool = load_pool(&env, pool_id);
+        assert!(pool.owner == owner, "Only pool owner can withdraw");
+        require_status(&env, &pool, &[PoolStatus::Finalized]);
+        let cumulative = uses_cumulative_claims(&pool);
+        let grace_period = if cumulative { CUMULATIVE_CLAIM_WINDOW } else { FINALIZE_GRACE_PERIOD };
+        assert!(
+            env.ledger().timestamp() >= pool.finalized_at + grace_period,
+            "Grace period not elapsed"
+        );
+
+        // Fim da janela de resgate: a reserva dos modos cumulativos expira e os patrocinadores
+        // recebem sua parte agora
+        if cumulative {
+            pool.reserved_rewards = 0;
+            let unused = pool.escrowed;
+            refund_sponsors(&env, pool_id, &mut pool, unused);
+        }
+
+        let unused = pool.escrowed - pool.reserved_rewards;
+        if unused <= 0 {
+            return 0;
+        }
+        pool.escrowed -= unused;
+        save_pool(&env, &pool);
+
+        let client = TokenClient::new(&env, &pool.reward_token);
+        client.transfer(&env.current_contract_address(), &owner, &unused);
+
+        unused
+    }
+
+    /// Retorna a delegação de um usuário em uma pool específica (None se a pool foi cancelada)
+    pub fn get_user_delegation(
+        env: Env,
+        user: Address,
+        pool_id: u64,
+    ) -> Option<Delegation> {
+        
+        load_delegation(&env, &user, pool_id).filter(|_| !is_cancelled(&env, pool_id))
+    }
+
+    /// Retorna o portfólio paginado do usuário: delegações com recompensas pendentes,
+    /// APY efetivo e status da pool. Pools canceladas ficam de fora.
+    pub fn get_user_delegations(
+        env: Env,
+        user: Address,
+        cursor: u32,
+        limit: u32,
+    ) -> Vec<DelegationInfo> {
+        let pool_ids: Vec<u64> =
+            persistent_get(&env, &DataKey::UserDelegations(user.clone())).unwrap_or(Vec::new(&env));
+
+        let limit = limit.min(MAX_PAGE_SIZE);
+        let end = cursor.saturating_add(limit).min(pool_ids.len());
+        let mut result = Vec::new(&env);
+
+        for i in cursor..end {
+            let pool_id = pool_ids.get_unchecked(i);
+            let pool = load_pool(&env, pool_id);
+            let delegation = match load_delegation(&env, &user, pool_id) {
+                Some(d) if pool.status != PoolStatus::Cancelled => d,
+                _ => continue,
+            };
+
+            result.push_back(DelegationInfo {
+                pending_rewards: pending_rewards(&env, &pool, &delegation),
+                effective_apy: effective_apy(&env, &pool),
+                pool_status: current_status(&env, &pool),
+                delegation,
+            });
+        }
+
+        result
+    }
+
+    /// Define a taxa do protocolo sobre recompensas resgatadas, em pontos base
+    pub fn set_protocol_fee(env: Env, admin: Address, fee_bps: u32) {
+        admin.require_auth();
+        let stored_admin: Address = env.storage().instance().get(&ADMIN).expect("Admin not set");
+        assert!(admin == stored_admin, "Only admin can set protocol fee");
+        assert!(fee_bps <= MAX_PROTOCOL_FEE_BPS, "Protocol fee above maximum");
+
+        env.storage().instance().set(&PROTOCOL_FEE, &fee_bps);
+    }
+
+    /// Taxa do protocolo atual, em pontos base
+    pub fn get_protocol_fee(env: Env) -> u32 {
+        env.storage().instance().get(&PROTOCOL_FEE).unwrap_or(0)
+    }
+
+    /// Taxas do protocolo acumuladas e ainda não sacadas em um token
+    pub fn get_protocol_fees_accrued(env: Env, token: Address) -> i128 {
+        let fees: Map<Address, i128> = env.storage().instance().get(&FEES_ACCRUED).unwrap_or(Map::new(&env));
+        fees.get(token).unwrap_or(0)
+    }
+
+    /// Envia ao tesouro as taxas acumuladas em um token (contrato -> tesouro)
+    pub fn withdraw_protocol_fees(env: Env, admin: Address, token: Address, treasury: Address) -> i128 {
+        admin.require_auth();
+        let stored_admin: Address = env.storage().instance().get(&ADMIN).expect("Admin not set");
+        assert!(admin == stored_admin, "Only admin can withdraw protocol fees");
+
+        let mut fees: Map<Address, i128> = env.storage().instance().get(&FEES_ACCRUED).unwrap_or(Map::new(&env));
+        let amount = fees.get(token.clone()).unwrap_or(0);
+        if amount <= 0 {
+            return 0;
+        }
+        fees.remove(token.clone());
+        env.storage().instance().set(&FEES_ACCRUED, &fees);
+
+        TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &treasury, &amount);
+        amount
+    }
+
+    /// Permite ao administrador pausar o contrato em caso de emergência
+    pub fn emergency_pause(env: Env, admin: Address) {
+        admin.require_auth();
+        
+        let stored_admin: Address = env.storage().instance().get(&ADMIN).expect("Admin not set");
+        assert!(admin == stored_admin, "Only admin can pause contract");
+        
+        // Implementar lógica de pausa de emergência
+        // Por simplicidade, não implementado neste exemplo
+    }
+}
+
+// Lê uma entrada persistente, renovando seu TTL quando ela existe
+fn persistent_get<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    let storage = env.storage().persistent();
+    let value = storage.get(key);
+    if value.is_some() {
+        storage.extend_ttl(key, PERSISTENT_TTL_THRESHOLD, PERSISTENT_TTL_EXTEND);
+    }
+    value
+}
+
+// Grava uma entrada persistente e renova seu TTL
+fn persistent_set<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    let storage = env.storage().persistent();
+    storage.set(key, value);
+    storage.extend_ttl(key, PERSISTENT_TTL_THRESHOLD, PERSISTENT_TTL_EXTEND);
+}
+
+// Pool armazenada, se existir
+fn stored_pool(env: &Env, pool_id: u64) -> Option<Pool> {
+    persistent_get(env, &DataKey::Pool(pool_id))
+}
+
+fn load_pool(env: &Env, pool_id: u64) -> Pool {
+    stored_pool(env, pool_id).expect("Pool not found")
+}
+
+fn save_pool(env: &Env, pool: &Pool) {
+    persistent_set(env, &DataKey::Pool(pool.id), pool);
+}
+
+// Acrescenta uma pool ao fim de um índice
+fn pool_index_push(env: &Env, index: PoolIndexKey, pool_id: u64) {
+    let len: u32 = persistent_get(env, &DataKey::PoolIndexLen(index.clone())).unwrap_or(0);
+    persistent_set(env, &DataKey::PoolIndexEntry(index.clone(), len), &pool_id);
+    persistent_set(env, &DataKey::PoolIndexLen(index), &(len + 1));
+}
+
+fn pool_index_get(env: &Env, index: &PoolIndexKey, position: u32) -> u64 {
+    persistent_get(env, &DataKey::PoolIndexEntry(index.clone(), position)).expect("Missing pool index entry")
+}
+
+// Status efetivo da pool, considerando o tempo atual do ledger
+fn current_status(env: &Env, pool: &Pool) -> PoolStatus {
+    let now = env.ledger().timestamp();
+    match pool.status {
+        PoolStatus::Scheduled | PoolStatus::Active | PoolStatus::Paused if now >= pool.end_time => PoolStatus::Ended,
+        PoolStatus::Scheduled | PoolStatus::Active if now < pool.start_time => PoolStatus::Scheduled,
+        PoolStatus::Scheduled => PoolStatus::Active,
+        status => status,
+    }
+}
+
+// Pool como vista agora: acumulador avançado e status calculado
+fn pool_view(env: &Env, pool: Pool) -> Pool {
+    let mut pool = accrue(env, pool);
+    pool.status = current_status(env, &pool);
+    pool
+}
+
+// Transições permitidas entre estados
+fn can_transition(from: PoolStatus, to: PoolStatus) -> bool {
+    matches!(
+        (from, to),
+        (PoolStatus::Active, PoolStatus::Paused)
+            | (PoolStatus::Paused, PoolStatus::Active)
+            | (PoolStatus::Scheduled, PoolStatus::Cancelled)
+            | (PoolStatus::Active, PoolStatus::Cancelled)
+            | (PoolStatus::Paused, PoolStatus::Cancelled)
+            | (PoolStatus::Ended, PoolStatus::Finalized)
+    )
+}
+
+fn set_status(env: &Env, pool: &mut Pool, to: PoolStatus) {
+    let from = current_status(env, pool);
+    assert!(can_transition(from, to), "Invalid pool status transition");
+    pool.status = to;
+}
+
+// Garante que a pool está em um dos estados permitidos para a operação
+fn require_status(env: &Env, pool: &Pool, allowed: &[PoolStatus]) {
+    let status = current_status(env, pool);
+    assert!(allowed.contains(&status), "Operation not allowed in current pool status");
+}
+
+// Folha Merkle: sha256(pool_id (8 bytes BE) || XDR do endereço || valor acumulado (16 bytes BE))
+fn merkle_leaf(env: &Env, pool_id: u64, user: &Address, cumulative_amount: i128) -> BytesN<32> {
+    let mut data = Bytes::from_array(env, &pool_id.to_be_bytes());
+    data.append(&user.clone().to_xdr(env));
+    data.extend_from_array(&cumulative_amount.to_be_bytes());
+    env.crypto().sha256(&data)
+}
+
+// Verifica a prova com pares ordenados: cada nó é sha256(menor || maior)
+fn verify_merkle_proof(env: &Env, leaf: BytesN<32>, proof: &Vec<BytesN<32>>, root: &BytesN<32>) -> bool {
+    let mut node = leaf;
+    for sibling in proof.iter() {
+        let (left, right) = if node <= sibling { (node, sibling) } else { (sibling, node) };
+        let mut data: Bytes = left.into();
+        data.append(&right.into());
+        node = env.crypto().sha256(&data);
+    }
+    &node == root
+}
+
+// Modos em que os direitos são cumulativos e comprovados off-chain (prova Merkle ou voucher)
+fn uses_cumulative_claims(pool: &Pool) -> bool {
+    pool.mode == DistributionMode::Merkle || pool.mode == DistributionMode::Voucher
+}
+
+// Indica se os saldos da pool vêm de um oráculo em vez do contrato de token
+fn uses_attested_balances(env: &Env, pool_id: u64) -> bool {
+    if persistent_get::<bool>(env, &DataKey::AttestedStake(pool_id)).unwrap_or(false) {
+        return true;
+    }
+    let oracles: Map<u64, Address> = env.storage().instance().get(&BALANCE_ORACLES).unwrap_or(Map::new(env));
+    oracles.contains_key(pool_id)
+}
+
+// Mensagem assinada do voucher: endereço do contrato || pool_id || endereço do usuário ||
+// valor acumulado || nonce || expiração, inteiros em big-endian
+fn voucher_message(env: &Env, pool_id: u64, user: &Address, cumulative_amount: i128, nonce: u64, expiry: u64) -> Bytes {
+    let mut data = env.current_contract_address().to_xdr(env);
+    data.extend_from_array(&pool_id.to_be_bytes());
+    data.append(&user.clone().to_xdr(env));
+    data.extend_from_array(&cumulative_amount.to_be_bytes());
+    data.extend_from_array(&nonce.to_be_bytes());
+    data.extend_from_array(&expiry.to_be_bytes());
+    data
+}
+
+// Paga a diferença entre o direito acumulado informado e o já resgatado (Merkle ou voucher)
+fn pay_cumulative(
+    env: &Env,
+    pool: &mut Pool,
+    claimed_key: &Symbol,
+    user: &Address,
+    cumulative_amount: i128,
+) -> i128 {
+    let mut claimed: Map<(Address, u64), i128> = env.storage().instance().get(claimed_key).unwrap_or(Map::new(env));
+    let already = claimed.get((user.clone(), pool.id)).unwrap_or(0);
+    let payout = cumulative_amount - already;
+    if payout <= 0 {
+        return 0;
+    }
+    // Depois da finalização, todo o custodiado está reservado para estes resgates
+    let finalized = current_status(env, pool) == PoolStatus::Finalized;
+    let available = if finalized { pool.escrowed } else { pool.escrowed - pool.reserved_rewards };
+    assert!(payout <= available, "Insufficient escrowed rewards");
+    if finalized {
+        pool.reserved_rewards -= payout.min(pool.reserved_rewards);
+    }
+
+    claimed.set((user.clone(), pool.id), cumulative_amount);
+    env.storage().instance().set(claimed_key, &claimed);
+
+    pool.distributed_amount += payout;
+    pool.escrowed -= payout;
+    save_pool(env, pool);
+
+    let net = take_protocol_fee(env, &pool.reward_token, payout);
+    if net > 0 {
+        let client = TokenClient::new(env, &pool.reward_token);
+        client.transfer(&env.current_contract_address(), user, &net);
+    }
+
+    net
+}
+
+// Move as recompensas acumuladas da delegação para o stake reinvestido. O valor sai do
+// escrow de recompensas e passa a compor `restaked` e o total delegado.
+fn compound_delegation(
+    env: &Env,
+    user: &Address,
+    pool_id: u64,
+) -> i128 {
+    let mut pool = load_pool(env, pool_id);
+    assert!(pool.stake_token == pool.reward_token, "Compounding requires the same stake and reward token");
+    require_status(env, &pool, &[PoolStatus::Active, PoolStatus::Paused]);
+
+    let mut delegation = match load_delegation(env, user, pool_id) {
+        Some(d) => d,
+        None => return 0,
+    };
+
+    pool = accrue(env, pool);
+    settle(&pool, &mut delegation);
+    let amount = delegation.accrued;
+    if amount <= 0 {
+        save_pool(env, &pool);
+        return 0;
+    }
+    assert!(amount <= pool.escrowed - pool.reserved_rewards, "Insufficient escrowed rewards");
+
+    // Reinvestir também é um pagamento de recompensa: só o valor líquido vira stake
+    let net = take_protocol_fee(env, &pool.reward_token, amount);
+    delegation.accrued = 0;
+    delegation.compounded += net;
+    delegation.last_claim = env.ledger().timestamp();
+
+    pool.distributed_amount += amount;
+    pool.escrowed -= amount;
+    pool.restaked += net;
+    pool.total_delegated += net;
+    if pool.mode == DistributionMode::EpochSnapshot {
+        record_epoch_snapshot(env, &pool, user, delegation.amount + delegation.compounded);
+    }
+
+    save_delegation(env, &delegation);
+    save_pool(env, &pool);
+    net
+}
+
+fn load_delegation(env: &Env, user: &Address, pool_id: u64) -> Option<Delegation> {
+    persistent_get(env, &DataKey::Delegation(user.clone(), pool_id))
+}
+
+fn save_delegation(env: &Env, delegation: &Delegation) {
+    persistent_set(env, &DataKey::Delegation(delegation.user.clone(), delegation.pool_id), delegation);
+}
+
+// Registra o opt-in e mantém o índice de pools por usuário
+fn record_opt_in(env: &Env, user: &Address, pool_id: u64) {
+    let pool = load_pool(env, pool_id);
+    require_status(env, &pool, &[PoolStatus::Scheduled, PoolStatus::Active]);
+    persistent_set(env, &DataKey::OptIn(user.clone(), pool_id), &true);
+
+    user_index_add(env, &DataKey::UserPools(user.clone()), pool_id);
+}
+
+fn is_opted_in(env: &Env, user: &Address, pool_id: u64) -> bool {
+    persistent_get(env, &DataKey::OptIn(user.clone(), pool_id)).unwrap_or(false)
+}
+
+// Adiciona uma pool ao índice do usuário (sem duplicar). Cada usuário tem sua própria chave,
+// então o custo depende só das pools dele.
+fn user_index_add(env: &Env, key: &DataKey, pool_id: u64) {
+    let mut pool_ids: Vec<u64> = persistent_get(env, key).unwrap_or(Vec::new(env));
+    if !pool_ids.contains(pool_id) {
+        pool_ids.push_back(pool_id);
+        persistent_set(env, key, &pool_ids);
+    }
+}
+
+// Indica se a pool foi cancelada; opt-ins e delegações nela não valem mais
+fn is_cancelled(env: &Env, pool_id: u64) -> bool {
+    stored_pool(env, pool_id).map_or(true, |pool| pool.status == PoolStatus::Cancelled)
+}
+
+// Avança o acumulador da pool até o tempo atual (sem persistir).
+// Uma mudança de APY que entrou em vigor no intervalo é aplicada com checkpoint no
+// momento exato da mudança, para que cada período use a taxa vigente.
+fn accrue(env: &Env, mut pool: Pool) -> Pool {
+    let now = env.ledger().timestamp().min(pool.end_time);
+    // A taxa só é consultada quando há algo a acumular, para não depender do oráculo à toa
+    let accruing = pool.status == PoolStatus::Scheduled || pool.status == PoolStatus::Active;
+    let rate = if accruing && pool.total_delegated > 0 && now > pool.last_update {
+        match conversion_rate(env, &pool) {
+            Some(rate) => {
+                if let ConversionRate::Oracle(_) | ConversionRate::UsdPrice(_) = pool.conversion {
+                    pool.last_rate = rate;
+                    pool.last_rate_at = env.ledger().timestamp();
+                }
+                rate
+            }
+            // Sem preço válido vale a última taxa aceita; sem nenhuma, o intervalo não acumula.
+            // Nunca aborta, para que o já acumulado continue resgatável.
+            None => pool.last_rate,
+        }
+    } else {
+        RATE_PRECISION
+    };
+    if pool.next_apy_at != 0 && pool.next_apy_at <= now {
+        pool = accrue_until(pool.clone(), pool.next_apy_at, rate);
+        pool.apy_rate = pool.next_apy_rate;
+        pool.max_apy = apy_rate_to_bps(pool.apy_rate);
+        pool.accrual_rate = accrual_rate(pool.apy_rate, pool.accrual).raw();
+        pool.next_apy_rate = 0;
+        pool.next_apy_at = 0;
+    }
+    let mut pool = accrue_until(pool, now, rate);
+    accrue_streams(&mut pool, now);
+    pool
+}
+
+// Acumula os fluxos adicionais até `to`, cada um limitado ao próprio orçamento.
+// Como no fluxo principal, não há acúmulo com a pool pausada ou sem delegações.
+fn accrue_streams(pool: &mut Pool, to: u64) {
+    let accruing = pool.status == PoolStatus::Scheduled || pool.status == PoolStatus::Active;
+    let mut streams = pool.streams.clone();
+    for i in 0..streams.len() {
+        let mut stream = streams.get_unchecked(i);
+        let to = to.min(stream.end_time);
+        if to <= stream.last_update {
+            continue;
+        }
+        if accruing && pool.total_delegated > 0 {
+            let precision = acc_precision(pool.stake_decimals, stream.decimals);
+            let emission = stream_emission(&stream, to) - stream_emission(&stream, stream.last_update);
+            let mut delta = mul_div_floor(emission, precision, pool.total_delegated);
+            let remaining = stream.total_rewards - stream.emitted;
+            let mut emitted = mul_div_ceil(pool.total_delegated, delta, precision);
+            if emitted > remaining {
+                delta = mul_div_floor(remaining.max(0), precision, pool.total_delegated);
+                emitted = mul_div_ceil(pool.total_delegated, delta, precision);
+            }
+            stream.reward_per_token += delta;
+            stream.emitted += emitted;
+        }
+        stream.last_update = to;
+        streams.set(i, stream);
+    }
+    pool.streams = streams;
+}
+
+// A taxa por unidade é o menor valor entre o APY máximo e a emissão da curva no intervalo
+// rateada pelo total delegado, limitada ao orçamento restante. Pools pausadas não acumulam.
+fn accrue_until(mut pool: Pool, to: u64, rate: i128) -> Pool {
+    match pool.mode {
+        DistributionMode::Continuous => {
+            let from = pool.last_update.max(pool.start_time);
+            if to <= from {
+                return pool;
+            }
+            accrue_interval(&mut pool, from, to, rate);
+        }
+        DistributionMode::EpochSnapshot => {
+            // Só épocas fechadas são rateadas, com o total delegado no fechamento. Todo sync faz
+            // checkpoint antes de alterar saldos, então o total e os saldos usados no rateio são
+            // os últimos registrados na época (`record_epoch_snapshot`).
+            let closed = epochs_elapsed(&pool, to);
+            while pool.epochs_closed < closed {
+                let epoch_start = pool.start_time + pool.epochs_closed as u64 * EPOCH_LENGTH;
+                accrue_interval(&mut pool, epoch_start, epoch_start + EPOCH_LENGTH, rate);
+                pool.epochs_closed += 1;
+            }
+        }
+        // Recompensas são pagas por prova Merkle, sem acúmulo on-chain
+        DistributionMode::Merkle | DistributionMode::Voucher => {}
+    }
+
+    pool.last_update = pool.last_update.max(to);
+    pool
+}
+
+fn accrue_interval(pool: &mut Pool, from: u64, to: u64, rate: i128) {
+    let accruing = pool.status == PoolStatus::Scheduled || pool.status == PoolStatus::Active;
+    if !accruing || pool.total_delegated <= 0 {
+        return;
+    }
+
+    // Arredondamento a favor da pool: a taxa por unidade arredonda para baixo e o valor
+    // reservado do orçamento (`emitted`) para cima
+    let elapsed = (to - from) as i128;
+    let precision = acc_precision(pool.stake_decimals, pool.reward_decimals);
+    // O APY incide sobre o stake; a taxa e as casas decimais convertem o resultado para
+    // unidades de recompensa. Com mais casas no stake, a diferença já está em `precision`.
+    let apy = Rate::from_raw(pool.accrual_rate);
+    let apy_delta = apy.apply(ACC_PRECISION * elapsed, Rounding::Floor) / SECONDS_PER_YEAR;
+    let apy_delta = mul_div_floor(apy_delta, rate, RATE_PRECISION);
+    let apy_delta = scale_decimals(apy_delta, pool.stake_decimals, pool.reward_decimals.max(pool.stake_decimals));
+    let emission = cumulative_emission(pool, to) - cumulative_emission(pool, from);
+    let emission_delta = mul_div_floor(emission, precision, pool.total_delegated);
+    let mut delta = apy_delta.min(emission_delta);
+
+    let remaining = pool.total_rewards - pool.emitted;
+    let mut emitted = mul_div_ceil(pool.total_delegated, delta, precision);
+    if emitted > remaining {
+        delta = mul_div_floor(remaining.max(0), precision, pool.total_delegated);
+        emitted = mul_div_ceil(pool.total_delegated, delta, precision);
+    }
+
+    pool.reward_per_token += delta;
+    pool.emitted += emitted;
+}
+
+// Número de épocas completamente decorridas até `t`
+fn epochs_elapsed(pool: &Pool, t: u64) -> u32 {
+    let t = t.clamp(pool.start_time, pool.end_time);
+    ((t - pool.start_time) / EPOCH_LENGTH) as u32
+}
+
+// Registra o total da pool e o saldo do usuário para a época corrente
+fn record_epoch_snapshot(env: &Env, pool: &Pool, user: &Address, balance: i128) {
+    let epoch = epochs_elapsed(pool, env.ledger().timestamp());
+    let pool_id = pool.id;
+    record_epoch_value(env, &DataKey::LastEpochTotal(pool_id), |e| DataKey::EpochTotal(pool_id, e), epoch, pool.total_delegated);
+    record_epoch_value(
+        env,
+        &DataKey::LastEpochBalance(user.clone(), pool_id),
+        |e| DataKey::EpochBalance(user.clone(), pool_id, e),
+        epoch,
+        balance,
+    );
+}
+
+// Grava o valor da época em armazenamento persistente, ligado ao registro anterior.
+// Um novo registro na mesma época substitui o anterior e mantém a ligação.
+fn record_epoch_value(env: &Env, last_key: &DataKey, key: impl Fn(u32) -> DataKey, epoch: u32, value: i128) {
+    let prev = match persistent_get::<u32>(env, last_key) {
+        Some(last) if last == epoch => persistent_get::<(i128, Option<u32>)>(env, &key(epoch)).and_then(|(_, prev)| prev),
+        last => last,
+    };
+    persistent_set(env, &key(epoch), &(value, prev));
+    persistent_set(env, last_key, &epoch);
+}
+
+// Valor vigente na época: o do último registro até ela, percorrendo só épocas registradas
+fn epoch_value_at(env: &Env, last_key: &DataKey, key: impl Fn(u32) -> DataKey, epoch: u32) -> i128 {
+    let mut cursor = persistent_get::<u32>(env, last_key);
+    while let Some(e) = cursor {
+        let (value, prev): (i128, Option<u32>) = persistent_get(env, &key(e)).expect("Missing epoch record");
+        if e <= epoch {
+            return value;
+        }
+        cursor = prev;
+    }
+    0
+}
+
+// Credita na delegação o que ela acumulou desde o último checkpoint. A fração que não forma
+// uma unidade inteira é carregada para o próximo checkpoint, então o total creditado não
+// depende da frequência dos resgates.
+fn settle(pool: &Pool, delegation: &mut Delegation) {
+    let delta = pool.reward_per_token - delegation.reward_per_token_paid;
+    let stake = delegation.amount + delegation.compounded;
+    let precision = acc_precision(pool.stake_decimals, pool.reward_decimals);
+    let (earned, remainder) = mul_div_rem(stake, delta, delegation.reward_remainder, precision);
+    delegation.accrued += earned;
+    delegation.reward_remainder = remainder;
+    delegation.reward_per_token_paid = pool.reward_per_token;
+
+    // Fluxos adicionados depois do último checkpoint começam do zero: o acumulador do fluxo
+    // também começou do zero e o stake não mudou desde então
+    let mut checkpoints = delegation.streams.clone();
+    for (i, stream) in pool.streams.iter().enumerate() {
+        let i = i as u32;
+        let mut checkpoint = checkpoints.get(i).unwrap_or(StreamCheckpoint {
+            reward_per_token_paid: 0,
+            accrued: 0,
+            remainder: 0,
+        });
+        let delta = stream.reward_per_token - checkpoint.reward_per_token_paid;
+        let precision = acc_precision(pool.stake_decimals, stream.decimals);
+        let (earned, remainder) = mul_div_rem(stake, delta, checkpoint.remainder, precision);
+        checkpoint.accrued += earned;
+        checkpoint.remainder = remainder;
+        checkpoint.reward_per_token_paid = stream.reward_per_token;
+        if i < checkpoints.len() {
+            checkpoints.set(i, checkpoint);
+        } else {
+            checkpoints.push_back(checkpoint);
+        }
+    }
+    delegation.streams = checkpoints;
+}
+
+// Separa a taxa do protocolo de um pagamento de recompensa. A taxa fica no contrato até ser
+// sacada pelo admin. Retorna o valor líquido.
+fn take_protocol_fee(env: &Env, token: &Address, amount: i128) -> i128 {
+    let fee_bps: u32 = env.storage().instance().get(&PROTOCOL_FEE).unwrap_or(0);
+    let fee = mul_div_floor(amount, fee_bps as i128, 10000);
+    if fee <= 0 {
+        return amount;
+    }
+    let mut fees: Map<Address, i128> = env.storage().instance().get(&FEES_ACCRUED).unwrap_or(Map::new(env));
+    fees.set(token.clone(), fees.get(token.clone()).unwrap_or(0) + fee);
+    env.storage().instance().set(&FEES_ACCRUED, &fees);
+    amount - fee
+}
+
+// Devolve aos patrocinadores a parte de `unused` proporcional às suas contribuições no total
+// depositado, arredondada para baixo. Retorna o total devolvido.
+fn refund_sponsors(env: &Env, pool_id: u64, pool: &mut Pool, unused: i128) -> i128 {
+    if pool.sponsored == 0 || unused <= 0 {
+        return 0;
+    }
+    let sponsors: Map<u64, Map<Address, i128>> = env.storage().instance().get(&SPONSORS).unwrap_or(Map::new(env));
+    let client = TokenClient::new(env, &pool.reward_token);
+    let mut refunded = 0;
+    for (sponsor, contribution) in sponsors.get(pool_id).unwrap_or(Map::new(env)).iter() {
+        let refund = mul_div_floor(unused, contribution, pool.deposited);
+        if refund > 0 {
+            client.transfer(&env.current_contract_address(), &sponsor, &refund);
+            env.events().publish((symbol_short!("sprefund"), pool_id), (sponsor, refund, pool.reward_decimals));
+            refunded += refund;
+        }
+    }
+    pool.escrowed -= refunded;
+    refunded
+}
+
+// Checkpoints de uma delegação nova: começa no valor atual de cada acumulador
+fn stream_checkpoints(env: &Env, pool: &Pool) -> Vec<StreamCheckpoint> {
+    let mut checkpoints = Vec::new(env);
+    for stream in pool.streams.iter() {
+        checkpoints.push_back(StreamCheckpoint {
+            reward_per_token_paid: stream.reward_per_token,
+            accrued: 0,
+            remainder: 0,
+        });
+    }
+    checkpoints
+}
+
+// Soma um pagamento ao detalhamento por token
+fn add_payout(payouts: &mut Map<Address, i128>, token: &Address, amount: i128) {
+    if amount > 0 {
+        payouts.set(token.clone(), payouts.get(token.clone()).unwrap_or(0) + amount);
+    }
+}
+
+// Recompensas acumuladas e ainda não resgatadas pela delegação
+fn pending_rewards(env: &Env, pool: &Pool, delegation: &Delegation) -> i128 {
+    let pool = accrue(env, pool.clone());
+    let mut delegation = delegation.clone();
+    settle(&pool, &mut delegation);
+    delegation.accrued
+}
+
+// APY efetivo estimado (bps): o menor entre o APY máximo e o que a emissão das
+// próximas 24h consegue pagar sobre o total delegado
+fn effective_apy(env: &Env, pool: &Pool) -> u32 {
+    if pool.total_delegated <= 0 {
+        return pool.max_apy;
+    }
+    let now = env.ledger().timestamp().max(pool.start_time);
+    let daily = cumulative_emission(pool, now + 86400) - cumulative_emission(pool, now);
+    let rate = conversion_rate(env, pool).unwrap_or(pool.last_rate);
+    let stake_value = mul_div_floor(pool.total_delegated, rate, RATE_PRECISION);
+    let stake_value = scale_decimals(stake_value, pool.stake_decimals, pool.reward_decimals);
+    if stake_value <= 0 {
+        return pool.max_apy;
+    }
+    let emission_apy = mul_div_floor(daily, 365 * 10000, stake_value);
+    if emission_apy < pool.max_apy as i128 {
+        emission_apy as u32
+    } else {
+        pool.max_apy
+    }
+}
+
+// Taxa anual simples usada pelo acumulador. No modelo composto é a taxa diária que, reinvestida
+// todo dia, rende exatamente o APY em um ano, multiplicada pelo número de dias.
+fn accrual_rate(apy_rate: i128, model: AccrualModel) -> Rate {
+    let apy = Rate::from_scaled(apy_rate, APY_RATE_SCALE);
+    match model {
+        AccrualModel::Simple => apy,
+        AccrualModel::Compounding => {
+            let daily = math::periodic_rate(apy, COMPOUNDING_PERIODS);
+            Rate::from_raw(daily.raw() * COMPOUNDING_PERIODS as i128)
+        }
+    }
+}
+
+// Converte APY em pontos base para a escala APY_RATE_SCALE
+fn bps_to_apy_rate(bps: u32) -> i128 {
+    bps as i128 * (APY_RATE_SCALE / 10000)
+}
+
+// APY em pontos base, arredondado para baixo, para visões e compatibilidade
+fn apy_rate_to_bps(apy_rate: i128) -> u32 {
+    (apy_rate / (APY_RATE_SCALE / 10000)) as u32
+}
+
+// Casas decimais e símbolo do token, ou None quando o endereço não responde como token
+fn token_metadata(env: &Env, token: &Address) -> Option<(u32, String)> {
+    let client = TokenClient::new(env, token);
+    let decimals = match client.try_decimals() {
+        Ok(Ok(decimals)) => decimals,
+        _ => return None,
+    };
+    let symbol = match client.try_symbol() {
+        Ok(Ok(symbol)) => symbol,
+        _ => String::from_str(env, ""),
+    };
+    Some((decimals, symbol))
+}
+
+// Precisão do acumulador por unidade delegada. Quando o stake tem mais casas decimais que a
+// recompensa, a recompensa por unidade de stake fica abaixo de 1 / ACC_PRECISION, então a
+// precisão cresce com a diferença.
+fn acc_precision(stake_decimals: u32, reward_decimals: u32) -> i128 {
+    ACC_PRECISION * 10i128.pow(stake_decimals.saturating_sub(reward_decimals))
+}
+
+// Converte um valor entre escalas de casas decimais (arredonda para baixo ao reduzir)
+fn scale_decimals(amount: i128, from: u32, to: u32) -> i128 {
+    if to >= from {
+        mul_div_floor(amount, 10i128.pow(to - from), 1)
+    } else {
+        mul_div_floor(amount, 1, 10i128.pow(from - to))
+    }
+}
+
+// Tokens de recompensa por token de stake (em unidades inteiras), escalado por RATE_PRECISION,
+// ou None quando o oráculo falha ou não tem um valor válido
+fn conversion_rate(env: &Env, pool: &Pool) -> Option<i128> {
+    match &pool.conversion {
+        ConversionRate::Parity => Some(RATE_PRECISION),
+        ConversionRate::Fixed(rate) => Some(*rate),
+        ConversionRate::Oracle(oracle) => {
+            match RateOracleClient::new(env, oracle).try_conversion_rate(&pool.stake_token, &pool.reward_token) {
+                Ok(Ok(rate)) if rate > 0 => Some(rate),
+                _ => None,
+            }
+        }
+        ConversionRate::UsdPrice(oracle) => {
+            let oracle = PriceOracleClient::new(env, oracle);
+            let stake_price = usd_price(env, &oracle, &pool.stake_token)?;
+            let reward_price = usd_price(env, &oracle, &pool.reward_token)?;
+            // Os dois preços têm as mesmas casas decimais do oráculo, que se cancelam na razão
+            let rate = mul_div_floor(stake_price, RATE_PRECISION, reward_price);
+            if pool.last_rate == 0 {
+                return Some(rate);
+            }
+            // A taxa se move no máximo o desvio por dia decorrido desde a última taxa aceita,
+            // então checkpoints frequentes não aceleram a aproximação de um preço distante
+            let elapsed = env.ledger().timestamp().saturating_sub(pool.last_rate_at) as i128;
+            let max_move = mul_div_floor(pool.last_rate, MAX_RATE_DEVIATION_BPS * elapsed, 10000 * RATE_DEVIATION_PERIOD);
+            Some(rate.clamp((pool.last_rate - max_move).max(0), pool.last_rate + max_move))
+        }
+    }
+}
+
+// Preço USD do token no oráculo SEP-40; None se ausente, inválido, antigo ou se o oráculo falhar
+fn usd_price(env: &Env, oracle: &PriceOracleClient, token: &Address) -> Option<i128> {
+    let data = match oracle.try_lastprice(&Asset::Stellar(token.clone())) {
+        Ok(Ok(Some(data))) => data,
+        _ => return None,
+    };
+    let now = env.ledger().timestamp();
+    let fresh = data.timestamp <= now && now - data.timestamp <= MAX_PRICE_AGE;
+    if data.price <= 0 || !fresh {
+        return None;
+    }
+    Some(data.price)
+}
+
+// Validações da curva de emissão
+fn validate_schedule(schedule: &EmissionSchedule, total_rewards: i128) {
+    match schedule {
+        EmissionSchedule::Constant => {}
+        EmissionSchedule::LinearDecay(end_rate_bps) => {
+            assert!(*end_rate_bps <= 10000, "End rate must be at most 100%");
+        }
+        EmissionSchedule::Stepwise(epoch_days, amounts) => {
+            assert!(*epoch_days > 0, "Epoch days must be positive");
+            assert!(!amounts.is_empty(), "Emission table must not be empty");
+            let mut sum = 0i128;
+            for amount in amounts.iter() {
+                assert!(amount >= 0, "Epoch emission must not be negative");
+                sum += amount;
+            }
+            assert!(sum <= total_rewards, "Emission table exceeds total rewards");
+        }
+        EmissionSchedule::Halving(period_days) => {
+            assert!(*period_days > 0, "Halving period must be positive");
+        }
+    }
+}
+
+// Emissão acumulada pela curva da pool entre o início e `t`.
+// As curvas são integradas de forma exata: a emissão de qualquer intervalo é a diferença
+// entre dois pontos desta função, então a soma de intervalos não depende do particionamento.
+fn cumulative_emission(pool: &Pool, t: u64) -> i128 {
+    curve_emission(&pool.schedule, pool.total_rewards, pool.daily_distribution, pool.start_time, pool.end_time, t)
+}
+
+// Emissão acumulada de um fluxo adicional entre o seu início e `t`
+fn stream_emission(stream: &RewardStream, t: u64) -> i128 {
+    curve_emission(&stream.schedule, stream.total_rewards, stream.daily_distribution, stream.start_time, stream.end_time, t)
+}
+
+// Emissão acumulada por uma curva com o orçamento e o período informados
+fn curve_emission(
+    schedule: &EmissionSchedule,
+    total_rewards: i128,
+    daily_distribution: i128,
+    start_time: u64,
+    end_time: u64,
+    t: u64,
+) -> i128 {
+    let t = t.clamp(start_time, end_time);
+    let elapsed = (t - start_time) as i128;
+    let duration = (end_time - start_time) as i128;
+    if duration == 0 {
+        return 0;
+    }
+
+    match schedule {
+        EmissionSchedule::Constant => mul_div_floor(daily_distribution, elapsed, 86400),
+        EmissionSchedule::LinearDecay(end_rate_bps) => {
+            // Fração emitida: (2x - (1 - f)x²) / (1 + f), com x = progresso e f = taxa final
+            let f = *end_rate_bps as i128;
+            let x = mul_div_floor(elapsed, ACC_PRECISION, duration);
+            let share = (2 * 10000 * x - mul_div_ceil((10000 - f) * x, x, ACC_PRECISION)) / (10000 + f);
+            mul_div_floor(total_rewards, share, ACC_PRECISION)
+        }
+        EmissionSchedule::Stepwise(epoch_days, amounts) => {
+            let epoch = *epoch_days as i128 * 86400;
+            let full_epochs = elapsed / epoch;
+            let mut total = 0i128;
+            for (i, amount) in amounts.iter().enumerate() {
+                let i = i as i128;
+                if i < full_epochs {
+                    total += amount;
+                } else {
+                    if i == full_epochs {
+                        total += mul_div_floor(amount, elapsed - i * epoch, epoch);
+                    }
+                    break;
+                }
+            }
+            total
+        }
+        EmissionSchedule::Halving(period_days) => {
+            // Curva unitária normalizada pelo total da vida da pool
+            let period = *period_days as i128 * 86400;
+            let full = halving_units(elapsed, period);
+            let whole = halving_units(duration, period);
+            mul_div_floor(total_rewards, full, whole)
+        }
+    }
+}
+
+// Emissão de uma curva de halving com ACC_PRECISION no primeiro período
+fn halving_units(elapsed: i128, period: i128) -> i128 {
+    let periods = elapsed / period;
+    let mut total = 0i128;
+    let mut rate = ACC_PRECISION;
+    let mut i = 0i128;
+    while i < periods && rate > 0 {
+        total += rate;
+        rate /= 2;
+        i += 1;
+    }
+    if rate > 0 {
+        total += rate * (elapsed - periods * period) / period;
+    }
+    total
+}
+
+// Verifica se a pool atende a todos os filtros informados.
+// Pools canceladas só aparecem quando o filtro de status pede explicitamente por elas.
+fn pool_matches(pool: &Pool, filters: &Vec<PoolFilter>) -> bool {
+    if pool.status == PoolStatus::Cancelled && !filters.contains(PoolFilter::Status(PoolStatus::Cancelled)) {
+        return false;
+    }
+    filters.iter().all(|filter| match filter {
+        PoolFilter::Status(status) => pool.status == status,
+        PoolFilter::Owner(owner) => pool.owner == owner,
+        PoolFilter::StakeToken(stake_token) => pool.stake_token == stake_token,
+        PoolFilter::RewardToken(reward_token) => pool.reward_token == reward_token,
+    })
+}
+
+// Atualiza a delegação do usuário e o total delegado da pool a partir do saldo lido
+fn apply_sync(
+    env: &Env,
+    user: &Address,
+    pool_id: u64,
+    balance: i128,
+) {
+    let current_time = env.ledger().timestamp();
+
+    // Checkpoint do acumulador antes de alterar o total delegado
+    let mut pool = accrue(env, load_pool(env, pool_id));
+    let mut delegation = load_delegation(env, user, pool_id).unwrap_or(Delegation {
+        user: user.clone(),
+        pool_id,
+        amount: 0,
+        timestamp: current_time,
+        last_claim: current_time,
+        accrued: 0,
+        reward_per_token_paid: pool.reward_per_token,
+        reward_remainder: 0,
+        compounded: 0,
+        streams: stream_checkpoints(env, &pool),
+    });
+    settle(&pool, &mut delegation);
+
+    // Atualiza total delegado; o stake reinvestido não depende do saldo externo
+    pool.total_delegated += balance - delegation.amount;
+    if pool.mode == DistributionMode::EpochSnapshot {
+        record_epoch_snapshot(env, &pool, user, balance + delegation.compounded);
+    }
+    save_pool(env, &pool);
+
+    // Atualiza delegação
+    delegation.amount = balance;
+    delegation.timestamp = current_time;
+    save_delegation(env, &delegation);
+    user_index_add(env, &DataKey::UserDelegations(user.clone()), pool_id);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::{testutils::{Address as _, Events, Ledger}, token, vec, Address, Env};
+
+    // Token simulado com 18 casas decimais, como um ativo de bridge
+    mod bridged_token {
+        use soroban_sdk::{contract, contractimpl, Env, String};
+
+        #[contract]
+        pub struct MockToken18;
+
+        #[contractimpl]
+        impl MockToken18 {
+            pub fn decimals(_env: Env) -> u32 {
+                18
+            }
+
+            pub fn symbol(env: Env) -> String {
+                String::from_str(&env, "WETH")
+            }
+        }
+    }
+
+    // Oráculo SEP-40 simulado: preços definidos manualmente com 14 casas decimais
+    #[contract]
+    pub struct MockPriceOracle;
+
+    #[contractimpl]
+    impl MockPriceOracle {
+        pub fn set_price(env: Env, token: Address, price: i128) {
+            let data = PriceData { price, timestamp: env.ledger().timestamp() };
+            env.storage().instance().set(&Asset::Stellar(token), &data);
+        }
+
+        pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+            env.storage().instance().get(&asset)
+        }
+
+        pub fn decimals(_env: Env) -> u32 {
+            14
+        }
+    }
+
+    // Oráculo de conversão simulado: taxa definida manualmente (escala RATE_PRECISION)
+    #[contract]
+    pub struct MockRateOracle;
+
+    #[contractimpl]
+    impl MockRateOracle {
+        pub fn set_rate(env: Env, rate: i128) {
+            env.storage().instance().set(&symbol_short!("RATE"), &rate);
+        }
+
+        pub fn conversion_rate(env: Env, _stake_token: Address, _reward_token: Address) -> i128 {
+            env.storage().instance().get(&symbol_short!("RATE")).unwrap_or(0)
+        }
+    }
+
+    // Cria um token de teste (Stellar Asset Contract) e faz mint para os endereços informados
+    fn create_token(env: &Env, admin: &Address, holders: &[(&Address, i128)]) -> Address {
+        let token_id = env.register_stellar_asset_contract(admin.clone());
+        let sac = token::StellarAssetClient::new(env, &token_id);
+        for (holder, amount) in holders {
+            sac.mint(holder, amount);
+        }
+        token_id
+    }
+
+    fn pool_ids(env: &Env, pools: Vec<Pool>) -> Vec<u64> {
+        let mut ids = Vec::new(env);
+        for pool in pools.iter() {
+            ids.push_back(pool.id);
+        }
+        ids
+    }
+
+    #[test]
+    fn test_create_pool() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+        
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+        
+        // Inicializar contrato
+        client.initialize(&admin);
+        
+        // Criar pool
+        let pool_id = client.create_pool(
+            &owner,
+            &token,
+            &token,
+            &1000000, // 1M tokens
+            &1500,    // 15% APY
+            &30,      // 30 dias
+            &None,    // início imediato
+            &EmissionSchedule::Constant,
+            &DistributionMode::Continuous,
+            &ConversionRate::Parity,
+        );
+        
+        assert_eq!(pool_id, 1);
+        
+        // Verificar pool criada
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.owner, owner);
+        assert_eq!(pool.total_rewards, 1000000);
+        assert_eq!(pool.max_apy, 1500);
+        assert_eq!(pool.distribution_days, 30);
+        assert_eq!(pool.status, PoolStatus::Active);
+    }
+
+    #[test]
+    fn test_delegate_and_claim() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+        
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+        
+        // Inicializar e criar pool
+        client.initialize(&admin);
+        let pool_id = client.create_pool(&owner, &token, &token, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        
+        // Usuário delega tokens
+        client.delegate_to_pool(&user, &pool_id, &10000);
+        
+        // Verificar delegação
+        let delegation = client.get_user_delegation(&user, &pool_id).unwrap();
+        assert_eq!(delegation.amount, 10000);
+        assert_eq!(delegation.user, user);
+        
+        // Simular passagem de tempo e calcular recompensas
+        // (Em um teste real, seria necessário manipular o timestamp)
+        let pending = client.calculate_pending_rewards(&user, &pool_id);
+        // As recompensas dependem do tempo, então apenas verificamos que não há erro
+        assert!(pending >= 0);
+    }
+
+    #[test]
+    fn test_opt_in_many_and_sync_all() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&user, 5000)]);
+        let usdc = create_token(&env, &admin, &[]);
+
+        client.initialize(&admin);
+        let p1 = client.create_pool(&owner, &kale, &usdc, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        let p2 = client.create_pool(&owner, &kale, &kale, &1000000, &1000, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        let p3 = client.create_pool(&owner, &usdc, &kale, &1000000, &1000, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+
+        client.opt_in_many(&user, &vec![&env, p1, p2]);
+        // Opt-in repetido não duplica o índice
+        client.opt_in(&user, &p1);
+        assert_eq!(client.get_user_pools(&user), vec![&env, p1, p2]);
+
+        assert_eq!(client.sync_all(&user), 2);
+        assert_eq!(client.get_user_delegation(&user, &p1).unwrap().amount, 5000);
+        assert_eq!(client.get_user_delegation(&user, &p2).unwrap().amount, 5000);
+        assert!(client.get_user_delegation(&user, &p3).is_none());
+        assert_eq!(client.get_pool(&p1).total_delegated, 5000);
+        assert_eq!(client.get_pool(&p2).total_delegated, 5000);
+    }
+
+    #[test]
+    fn test_get_user_delegations_paginated() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+
+        client.initialize(&admin);
+        for _ in 0..3 {
+            let pool_id = client.create_pool(&owner, &token, &token, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+            client.delegate_to_pool(&user, &pool_id, &10000);
+        }
+
+        env.ledger().with_mut(|l| l.timestamp += 10 * 86400);
+
+        let page = client.get_user_delegations(&user, &0, &2);
+        assert_eq!(page.len(), 2);
+        let first = page.get_unchecked(0);
+        assert_eq!(first.delegation.pool_id, 1);
+        assert_eq!(first.pending_rewards, 41);
+        assert_eq!(first.effective_apy, 1500);
+        assert_eq!(first.pool_status, PoolStatus::Active);
+
+        let rest = client.get_user_delegations(&user, &2, &2);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest.get_unchecked(0).delegation.pool_id, 3);
+        assert_eq!(client.get_user_delegations(&user, &3, &2).len(), 0);
+    }
+
+    #[test]
+    fn test_list_pools_paginated_and_filtered() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[]);
+
+        client.initialize(&admin);
+        client.create_pool(&alice, &kale, &usdc, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity); // 1
+        client.create_pool(&bob, &kale, &kale, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);   // 2
+        client.create_pool(&alice, &usdc, &kale, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity); // 3
+        client.create_pool(&alice, &kale, &kale, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity); // 4
+        client.toggle_pool_status(&alice, &4);
+
+        let (page, cursor) = client.list_pools(&0, &3, &vec![&env]);
+        assert_eq!((page.len(), cursor), (3, 3));
+        let (next, cursor) = client.list_pools(&cursor, &3, &vec![&env]);
+        assert_eq!((pool_ids(&env, next), cursor), (vec![&env, 4], 0));
+
+        let by_owner = vec![&env, PoolFilter::Owner(alice.clone())];
+        assert_eq!(pool_ids(&env, client.list_pools(&0, &10, &by_owner).0), vec![&env, 1, 3, 4]);
+        assert_eq!(pool_ids(&env, client.list_pools(&1, &10, &by_owner).0), vec![&env, 3, 4]);
+
+        let kale_active = vec![&env, PoolFilter::Status(PoolStatus::Active), PoolFilter::StakeToken(kale.clone())];
+        assert_eq!(pool_ids(&env, client.list_pools(&0, &10, &kale_active).0), vec![&env, 1, 2]);
+
+        let alice_pays_kale = vec![&env, PoolFilter::RewardToken(kale.clone()), PoolFilter::Owner(alice.clone())];
+        assert_eq!(pool_ids(&env, client.list_pools(&0, &10, &alice_pays_kale).0), vec![&env, 3, 4]);
+
+        // A busca percorre um número limitado de pools por chamada: filtros sem correspondência
+        // devolvem uma página vazia com o cursor para continuar
+        for _ in 0..MAX_POOLS_SCANNED {
+            env.budget().reset_default();
+            client.create_pool(&bob, &kale, &kale, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        }
+        let last = client.create_pool(&alice, &usdc, &usdc, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        let pays_usdc = vec![&env, PoolFilter::RewardToken(usdc.clone())];
+        let (page, cursor) = client.list_pools(&4, &10, &pays_usdc);
+        assert_eq!((page.len(), cursor), (0, 4 + MAX_POOLS_SCANNED as u64));
+        let (page, cursor) = client.list_pools(&cursor, &10, &pays_usdc);
+        assert_eq!((pool_ids(&env, page), cursor), (vec![&env, last], 0));
+    }
+
+    #[test]
+    fn test_pool_status_transitions() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+
+        client.initialize(&admin);
+        let pool_id = client.create_pool(&owner, &token, &token, &1000000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+
+        client.toggle_pool_status(&owner, &pool_id);
+        assert_eq!(client.get_pool(&pool_id).status, PoolStatus::Paused);
+        assert_eq!(client.get_active_pools().len(), 0);
+
+        client.toggle_pool_status(&owner, &pool_id);
+        assert_eq!(client.get_pool(&pool_id).status, PoolStatus::Active);
+        client.delegate_to_pool(&user, &pool_id, &10000);
+
+        // Depois do fim, a pool fica Ended e sai da lista de ativas
+        env.ledger().with_mut(|l| l.timestamp += 31 * 86400);
+        assert_eq!(client.get_pool(&pool_id).status, PoolStatus::Ended);
+        assert_eq!(client.get_active_pools().len(), 0);
+
+        // O acúmulo para no fim da pool
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 10000 * 1500 / 10000 * 30 / 365);
+    }
+
+    #[test]
+    fn test_scheduled_pool_does_not_accrue_before_start() {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().with_mut(|l| l.timestamp = 1_000_000);
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&user, 100000)]);
+
+        client.initialize(&admin);
+        let start = 1_000_000 + 2 * 86400;
+        let pool_id = client.create_pool(&owner, &kale, &kale, &1000000, &1500, &30, &Some(start), &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.status, PoolStatus::Scheduled);
+        assert_eq!(pool.start_time, start);
+        assert_eq!(pool.end_time, start + 30 * 86400);
+
+        // Opt-in e sync são permitidos antes do início, mas nada acumula
+        client.opt_in(&user, &pool_id);
+        client.sync_delegation(&user, &pool_id);
+        env.ledger().with_mut(|l| l.timestamp = start - 1);
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 0);
+
+        env.ledger().with_mut(|l| l.timestamp = start + 10 * 86400);
+        assert_eq!(client.get_pool(&pool_id).status, PoolStatus::Active);
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 100000 * 1500 / 10000 * 10 / 365);
+    }
+
+    #[test]
+    fn test_cancel_scheduled_pool_refunds_escrow() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&user, 100000)]);
+        let usdc = create_token(&env, &admin, &[(&owner, 50000)]);
+
+        client.initialize(&admin);
+        let start = env.ledger().timestamp() + 86400;
+        let pool_id = client.create_pool(&owner, &kale, &usdc, &50000, &1500, &30, &Some(start), &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &pool_id, &50000);
+        client.opt_in(&user, &pool_id);
+        client.sync_delegation(&user, &pool_id);
+
+        assert_eq!(client.cancel_pool(&owner, &pool_id), 50000);
+        assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 50000);
+
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.status, PoolStatus::Cancelled);
+        assert_eq!(pool.escrowed, 0);
+        assert_eq!(client.get_user_pools(&user).len(), 0);
+        assert!(client.get_user_delegation(&user, &pool_id).is_none());
+        assert_eq!(client.list_pools(&0, &10, &vec![&env]).0.len(), 0);
+        let cancelled = vec![&env, PoolFilter::Status(PoolStatus::Cancelled)];
+        assert_eq!(client.list_pools(&0, &10, &cancelled).0.len(), 1);
+    }
+
+    #[test]
+    fn test_finalize_and_withdraw_unused_rewards() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&user, 1_000_000)]);
+        let usdc = create_token(&env, &admin, &[(&owner, 100_000)]);
+        let usdc_client = token::Client::new(&env, &usdc);
+
+        client.initialize(&admin);
+        let pool_id = client.create_pool(&owner, &kale, &usdc, &100_000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &pool_id, &100_000);
+        client.opt_in(&user, &pool_id);
+        client.sync_delegation(&user, &pool_id);
+
+        env.ledger().with_mut(|l| l.timestamp += 40 * 86400);
+        let earned = 1_000_000 * 1500 / 10000 * 30 / 365;
+        // A reserva é o total acumulado pela pool, que arredonda a favor dela: cobre o devido
+        // com até uma unidade de folga
+        let reserved = earned + 1;
+        assert_eq!(client.finalize_pool(&pool_id), reserved);
+
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.status, PoolStatus::Finalized);
+        assert_eq!(pool.reserved_rewards, reserved);
+
+        // Sobras só depois da carência
+        env.ledger().with_mut(|l| l.timestamp += 7 * 86400);
+        assert_eq!(client.withdraw_unused_rewards(&owner, &pool_id), 100_000 - reserved);
+        assert_eq!(usdc_client.balance(&owner), 100_000 - reserved);
+        assert_eq!(client.withdraw_unused_rewards(&owner, &pool_id), 0);
+
+        // O delegador ainda resgata o que ganhou
+        assert_eq!(client.claim_rewards(&user, &pool_id).get(usdc.clone()), Some(earned));
+        assert_eq!(usdc_client.balance(&user), earned);
+        let pool = client.get_pool(&pool_id);
+        assert_eq!((pool.reserved_rewards, pool.escrowed), (1, 1));
+    }
+
+    #[test]
+    fn test_extend_pool_applies_new_rate_from_now() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[(&owner, 60_000)]);
+
+        // APY alto: a emissão diária (1000/dia) é quem limita a taxa
+        client.initialize(&admin);
+        let pool_id = client.create_pool(&owner, &token, &token, &30_000, &10000, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.delegate_to_pool(&user, &pool_id, &1_000_000);
+
+        env.ledger().with_mut(|l| l.timestamp += 10 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 10_000);
+
+        // +30000 e +10 dias: sobram 50000 para os próximos 30 dias
+        client.extend_pool(&owner, &pool_id, &30_000, &10);
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.end_time, pool.start_time + 40 * 86400);
+        assert_eq!(pool.daily_distribution, 50_000 / 30);
+        assert_eq!(pool.escrowed, 30_000);
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 10_000);
+
+        client.deposit_rewards(&owner, &pool_id, &30_000);
+        env.ledger().with_mut(|l| l.timestamp += 30 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), 10_000 + 1_666 * 30);
+        assert_eq!(client.claim_rewards(&user, &pool_id).get(token.clone()), Some(59_980));
+    }
+
+    #[test]
+    fn test_apy_change_applies_after_notice_period() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+
+        client.initialize(&admin);
+        let pool_id = client.create_pool(&owner, &token, &token, &1_000_000_000, &1000, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.delegate_to_pool(&user, &pool_id, &1_000_000);
+
+        let effective_at = client.propose_apy_change(&pool_id, &2000);
+        assert_eq!(effective_at, env.ledger().timestamp() + 7 * 86400);
+        assert_eq!(
+            client.get_pending_apy_change(&pool_id),
+            Some(ApyChange { new_apy: 2000, new_apy_rate: 200_000_000, effective_at })
+        );
+        assert_eq!(client.get_pool(&pool_id).max_apy, 1000);
+
+        // 7 dias a 10% e 7 dias a 20%
+        env.ledger().with_mut(|l| l.timestamp += 14 * 86400);
+        let first = 1000 * ACC_PRECISION * 7 * 86400 / (10000 * SECONDS_PER_YEAR);
+        let second = 2000 * ACC_PRECISION * 7 * 86400 / (10000 * SECONDS_PER_YEAR);
+        let expected = 1_000_000 * (first + second) / ACC_PRECISION;
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), expected);
+        assert_eq!(client.get_pool(&pool_id).max_apy, 2000);
+        assert_eq!(client.get_pending_apy_change(&pool_id), None);
+    }
+
+    #[test]
+    fn test_emission_schedules() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        let t0 = env.ledger().timestamp();
+        let day = 86400u64;
+
+        // Halving a cada 10 dias: 40000, 20000, 10000
+        let halving = client.create_pool(&owner, &token, &token, &70_000, &10000, &30, &None, &EmissionSchedule::Halving(10), &DistributionMode::Continuous, &ConversionRate::Parity);
+        assert_eq!(client.preview_emission(&halving, &t0, &(t0 + 10 * day)), 40_000);
+        assert_eq!(client.preview_emission(&halving, &(t0 + 10 * day), &(t0 + 20 * day)), 20_000);
+        assert_eq!(client.preview_emission(&halving, &t0, &(t0 + 30 * day)), 70_000);
+
+        // Decaimento linear até zero: 3/4 do total na primeira metade
+        let linear = client.create_pool(&owner, &token, &token, &30_000, &10000, &30, &None, &EmissionSchedule::LinearDecay(0), &DistributionMode::Continuous, &ConversionRate::Parity);
+        assert_eq!(client.preview_emission(&linear, &t0, &(t0 + 15 * day)), 22_500);
+        assert_eq!(client.preview_emission(&linear, &t0, &(t0 + 30 * day)), 30_000);
+
+        // Tabela por época de 10 dias
+        let table = vec![&env, 10_000i128, 5_000, 1_000];
+        let stepwise = client.create_pool(&owner, &token, &token, &16_000, &10000, &30, &None, &EmissionSchedule::Stepwise(10, table), &DistributionMode::Continuous, &ConversionRate::Parity);
+        assert_eq!(client.preview_emission(&stepwise, &(t0 + 5 * day), &(t0 + 15 * day)), 7_500);
+
+        // O acumulador segue a curva
+        client.delegate_to_pool(&user, &halving, &1_000_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 5 * 86400);
+        client.delegate_to_pool(&user, &halving, &1_000_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 10 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&user, &halving), 50_000);
+    }
+
+    #[test]
+    fn test_epoch_snapshot_mode() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let token = create_token(&env, &admin, &[(&owner, 30_000)]);
+        client.initialize(&admin);
+
+        // 1000 por época, APY alto para não limitar
+        let pool_id = client.create_pool(
+            &owner, &token, &token, &30_000, &10000, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::EpochSnapshot, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &30_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+
+        // Época aberta não paga nada
+        env.ledger().with_mut(|l| l.timestamp += 43200);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 0);
+
+        // Bob entra no fim da época 0 e divide o fechamento com Alice
+        client.delegate_to_pool(&bob, &pool_id, &3_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 43200);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 250);
+        assert_eq!(client.calculate_pending_rewards(&bob, &pool_id), 750);
+
+        let snapshot = client.get_epoch_snapshot(&pool_id, &0, &bob);
+        assert_eq!(snapshot.total_delegated, 4_000_000);
+        assert_eq!(snapshot.user_balance, 3_000_000);
+        assert_eq!(snapshot.emission, 1_000);
+        assert!(snapshot.closed);
+
+        // Épocas seguintes herdam os saldos; claim paga as épocas fechadas
+        env.ledger().with_mut(|l| l.timestamp += 2 * 86400 + 100);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &2, &alice).user_balance, 1_000_000);
+        assert_eq!(client.claim_rewards(&alice, &pool_id).get(token.clone()), Some(750));
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 0);
+
+        // Um novo registro não altera o histórico das épocas anteriores
+        client.delegate_to_pool(&bob, &pool_id, &1_000_000);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &3, &bob).user_balance, 1_000_000);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &3, &bob).total_delegated, 2_000_000);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &1, &bob).user_balance, 3_000_000);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &1, &bob).total_delegated, 4_000_000);
+
+        // Reinvestir e sacar o reinvestido também atualizam a época corrente
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert_eq!(client.compound(&alice, &pool_id), 500);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &4, &alice).user_balance, 1_000_500);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &4, &alice).total_delegated, 2_000_500);
+        assert_eq!(client.withdraw_compounded(&alice, &pool_id), 500);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &4, &alice).user_balance, 1_000_000);
+        assert_eq!(client.get_epoch_snapshot(&pool_id, &4, &alice).total_delegated, 2_000_000);
+    }
+
+    // Nó interno da árvore Merkle, com o mesmo ordenamento usado pelo contrato
+    fn merkle_node(env: &Env, a: &BytesN<32>, b: &BytesN<32>) -> BytesN<32> {
+        let (left, right) = if a <= b { (a.clone(), b.clone()) } else { (b.clone(), a.clone()) };
+        let mut data: Bytes = left.into();
+        data.append(&right.into());
+        env.crypto().sha256(&data)
+    }
+
+    #[test]
+    fn test_merkle_claims_are_cumulative() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let distributor = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[(&owner, 10_000)]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &usdc, &10_000, &1500, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Merkle, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &10_000);
+        client.set_distributor(&pool_id, &distributor);
+
+        // Época 1: alice 100, bob 50
+        let a1 = merkle_leaf(&env, pool_id, &alice, 100);
+        let b1 = merkle_leaf(&env, pool_id, &bob, 50);
+        client.post_merkle_root(&distributor, &pool_id, &1, &merkle_node(&env, &a1, &b1));
+        assert_eq!(client.claim_merkle(&alice, &pool_id, &100, &vec![&env, b1.clone()]), 100);
+
+        // Repetir a mesma prova não paga de novo
+        assert_eq!(client.claim_merkle(&alice, &pool_id, &100, &vec![&env, b1]), 0);
+
+        // Época 2: alice 250, bob 120; alice recebe só a diferença
+        let a2 = merkle_leaf(&env, pool_id, &alice, 250);
+        let b2 = merkle_leaf(&env, pool_id, &bob, 120);
+        client.post_merkle_root(&owner, &pool_id, &2, &merkle_node(&env, &a2, &b2));
+        assert_eq!(client.claim_merkle(&alice, &pool_id, &250, &vec![&env, b2]), 150);
+        assert_eq!(client.claim_merkle(&bob, &pool_id, &120, &vec![&env, a2]), 120);
+
+        assert_eq!(client.get_merkle_claimed(&alice, &pool_id), 250);
+        assert_eq!(token::Client::new(&env, &usdc).balance(&alice), 250);
+        assert_eq!(client.get_pool(&pool_id).escrowed, 10_000 - 370);
+    }
+
+    // Assina um voucher com a mesma mensagem verificada pelo contrato
+    fn sign_voucher(
+        env: &Env,
+        contract_id: &Address,
+        key: &SigningKey,
+        pool_id: u64,
+        user: &Address,
+        cumulative_amount: i128,
+        nonce: u64,
+        expiry: u64,
+    ) -> BytesN<64> {
+        let message = env.as_contract(contract_id, || {
+            voucher_message(env, pool_id, user, cumulative_amount, nonce, expiry)
+        });
+        let mut raw = [0u8; 256];
+        let raw = &mut raw[..message.len() as usize];
+        message.copy_into_slice(raw);
+        BytesN::from_array(env, &key.sign(raw).to_bytes())
+    }
+
+    #[test]
+    fn test_claim_with_voucher_and_rotate_signer() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[(&owner, 10_000)]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &usdc, &10_000, &1500, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Voucher, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &10_000);
+
+        let backend = SigningKey::from_bytes(&[7u8; 32]);
+        client.set_voucher_signer(&pool_id, &BytesN::from_array(&env, &backend.verifying_key().to_bytes()));
+
+        let expiry = env.ledger().timestamp() + 3600;
+        let sig = sign_voucher(&env, &contract_id, &backend, pool_id, &alice, 300, 1, expiry);
+        assert_eq!(client.claim_with_voucher(&alice, &pool_id, &300, &1, &expiry, &sig), 300);
+        assert_eq!(client.get_voucher_nonce(&alice, &pool_id), Some(1));
+
+        // Após a rotação, vouchers da nova chave pagam só a diferença acumulada
+        let rotated = SigningKey::from_bytes(&[9u8; 32]);
+        client.rotate_voucher_signer(&admin, &pool_id, &BytesN::from_array(&env, &rotated.verifying_key().to_bytes()));
+        let sig = sign_voucher(&env, &contract_id, &rotated, pool_id, &alice, 500, 2, expiry);
+        assert_eq!(client.claim_with_voucher(&alice, &pool_id, &500, &2, &expiry, &sig), 200);
+
+        assert_eq!(token::Client::new(&env, &usdc).balance(&alice), 500);
+        assert_eq!(client.get_pool(&pool_id).distributed_amount, 500);
+    }
+
+    #[test]
+    fn test_attested_balances_drive_delegations() {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().with_mut(|li| li.timestamp = 10_000);
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let oracle = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let outsider = Address::generate(&env);
+        let btc = Address::generate(&env);
+        let usdc = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // O oráculo só pode ser definido antes do início da pool
+        let pool_id = client.create_pool(
+            &owner, &btc, &usdc, &1_000_000, &1500, &30, &Some(10_050),
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.set_balance_oracle(&pool_id, &oracle);
+        // Sem contrato de token, o stake assume as casas decimais padrão
+        assert_eq!(client.get_pool(&pool_id).stake_decimals, DEFAULT_TOKEN_DECIMALS);
+        client.opt_in(&alice, &pool_id);
+        client.opt_in(&bob, &pool_id);
+
+        // Usuários sem opt-in são ignorados
+        let batch = vec![&env, (alice.clone(), 600), (bob.clone(), 400), (outsider.clone(), 50)];
+        assert_eq!(client.attest_balances(&pool_id, &batch, &9_900), 2);
+        assert_eq!(client.get_pool(&pool_id).total_delegated, 1000);
+        assert_eq!(client.get_last_attestation(&pool_id), Some(9_900));
+
+        // Um atestado mais recente substitui os saldos anteriores
+        env.ledger().with_mut(|li| li.timestamp = 12_000);
+        assert_eq!(client.attest_balances(&pool_id, &vec![&env, (alice.clone(), 100)], &11_500), 1);
+        assert_eq!(client.get_user_delegation(&alice, &pool_id).unwrap().amount, 100);
+        assert_eq!(client.get_pool(&pool_id).total_delegated, 500);
+
+        // sync_all não consulta o token de stake de pools atestadas
+        assert_eq!(client.sync_all(&alice), 0);
+    }
+
+    #[test]
+    fn test_fixed_conversion_rate_scales_apy() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // 1 KALE vale 0,5 USDC: 10% ao ano sobre 1_000_000 KALE paga 50_000 USDC
+        let halved = client.create_pool(
+            &owner, &kale, &usdc, &1_000_000_000, &1000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Fixed(RATE_PRECISION / 2),
+        );
+        let parity = client.create_pool(
+            &owner, &kale, &kale, &1_000_000_000, &1000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.delegate_to_pool(&alice, &halved, &1_000_000);
+        client.delegate_to_pool(&alice, &parity, &1_000_000);
+
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &parity), 100_000);
+        assert_eq!(client.calculate_pending_rewards(&alice, &halved), 50_000);
+    }
+
+    #[test]
+    fn test_rate_oracle_conversion() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+        let oracle_id = env.register_contract(None, MockRateOracle);
+        let oracle = MockRateOracleClient::new(&env, &oracle_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // 1 KALE vale 2 USDC
+        oracle.set_rate(&(2 * RATE_PRECISION));
+        let pool_id = client.create_pool(
+            &owner, &kale, &usdc, &1_000_000_000, &1000, &1000, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Oracle(oracle_id.clone()),
+        );
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 200_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, 2 * RATE_PRECISION);
+
+        // Taxa inválida do oráculo não aborta: vale a última taxa aceita
+        oracle.set_rate(&0);
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 400_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, 2 * RATE_PRECISION);
+    }
+
+    #[test]
+    fn test_usd_price_oracle_limits_rate_jumps() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+        let oracle_id = env.register_contract(None, MockPriceOracle);
+        let oracle = MockPriceOracleClient::new(&env, &oracle_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // KALE a US$ 0,50 e USDC a US$ 1,00
+        oracle.set_price(&kale, &50_000_000_000_000);
+        oracle.set_price(&usdc, &100_000_000_000_000);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &usdc, &1_000_000_000, &1000, &730, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::UsdPrice(oracle_id.clone()),
+        );
+        assert_eq!(client.get_usd_prices(&pool_id), (50_000_000_000_000, 100_000_000_000_000, 14));
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+
+        // 10% ao ano sobre US$ 500_000 em KALE paga 50_000 USDC
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        oracle.set_price(&kale, &50_000_000_000_000);
+        oracle.set_price(&usdc, &100_000_000_000_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 50_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, RATE_PRECISION / 2);
+
+        // KALE dobra de preço, mas em um dia a taxa só sobe até o desvio máximo (0,5 -> 0,6)
+        env.ledger().with_mut(|li| li.timestamp += 86400);
+        oracle.set_price(&kale, &100_000_000_000_000);
+        oracle.set_price(&usdc, &100_000_000_000_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, 600_000_000);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 50_164);
+
+        // Checkpoints seguidos não aceleram: em 5 segundos a taxa anda só 20% * 5 / 86400
+        env.ledger().with_mut(|li| li.timestamp += 5);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, 600_006_944);
+
+        // Com o preço antigo, o acúmulo segue com a última taxa aceita em vez de abortar
+        env.ledger().with_mut(|li| li.timestamp += 100 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 50_164 + 16_438);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.get_pool(&pool_id).last_rate, 600_006_944);
     }
 
-    /// Retorna a delegação de um usuário em uma pool específica
-    pub fn get_user_delegation(
-        env: Env,
-        user: Address,
-        pool_id: u64,
-    ) -> Option<Delegation> {
-        let delegations: Map<(Address, u64), Delegation> = 
-            env.storage().instance().get(&DELEGATIONS).unwrap_or(Map::new(&env));
-        
-        let delegation_key = (user, pool_id);
-        delegations.get(delegation_key)
+    #[test]
+    fn test_token_decimals_normalize_apy() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let weth = env.register_contract(None, bridged_token::MockToken18);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &weth, &1_000_000_000_000_000_000_000, &1000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        let pool = client.get_pool(&pool_id);
+        assert_eq!((pool.stake_decimals, pool.reward_decimals), (7, 18));
+        assert_eq!(pool.reward_symbol, String::from_str(&env, "WETH"));
+
+        // 100 KALE (7 casas) a 10% ao ano rendem 10 WETH (18 casas)
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000_000);
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 10_000_000_000_000_000_000);
     }
 
-    /// Permite ao administrador pausar o contrato em caso de emergência
-    pub fn emergency_pause(env: Env, admin: Address) {
-        admin.require_auth();
-        
-        let stored_admin: Address = env.storage().instance().get(&ADMIN).expect("Admin not set");
-        assert!(admin == stored_admin, "Only admin can pause contract");
-        
-        // Implementar lógica de pausa de emergência
-        // Por simplicidade, não implementado neste exemplo
+    #[test]
+    fn test_token_decimals_stake_with_more_decimals() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let weth = env.register_contract(None, bridged_token::MockToken18);
+        let kale = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &weth, &kale, &1_000_000_000_000, &1000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+
+        // 1000 WETH (18 casas) a 10% ao ano, com checkpoint diário por 30 dias
+        let stake = 1_000_000_000_000_000_000_000i128;
+        client.delegate_to_pool(&alice, &pool_id, &stake);
+        for _ in 0..30 {
+            env.ledger().with_mut(|li| li.timestamp += 86400);
+            client.delegate_to_pool(&alice, &pool_id, &stake);
+        }
+        // 100 KALE (7 casas) por ano
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 1_000_000_000 * 30 / 365);
+
+        // E mais 300 dias sem checkpoint (uma unidade perdida no arredondamento de cada intervalo)
+        env.ledger().with_mut(|li| li.timestamp += 300 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 1_000_000_000 * 330 / 365 - 1);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use soroban_sdk::{testutils::Address as _, Address, Env};
+    #[test]
+    fn test_extreme_balances_do_not_overflow() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // Saldo de 1e30 unidades: o produto saldo * acumulador passa de i128
+        let stake = 1_000_000_000_000_000_000_000_000_000_000i128;
+        let pool_id = client.create_pool(
+            &owner, &token, &token, &(stake * 100), &1000, &3650, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.delegate_to_pool(&alice, &pool_id, &stake);
+
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), stake / 10);
+    }
 
     #[test]
-    fn test_create_pool() {
+    fn test_frequent_checkpoints_keep_fractional_rewards() {
         let env = Env::default();
+        env.mock_all_auths();
         let contract_id = env.register_contract(None, PoolRewardsContract);
         let client = PoolRewardsContractClient::new(&env, &contract_id);
-        
+
         let admin = Address::generate(&env);
         let owner = Address::generate(&env);
-        let token = Address::generate(&env);
-        
-        // Inicializar contrato
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
         client.initialize(&admin);
-        
-        // Criar pool
+
         let pool_id = client.create_pool(
-            &owner,
-            &token,
-            &1000000, // 1M tokens
-            &1500,    // 15% APY
-            &30,      // 30 dias
+            &owner, &token, &token, &1_000_000, &1500, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.delegate_to_pool(&alice, &pool_id, &1000);
+        client.delegate_to_pool(&bob, &pool_id, &1000);
+
+        // Alice faz checkpoint todo dia (~0,41 por dia); Bob nunca
+        for _ in 0..30 {
+            env.ledger().with_mut(|li| li.timestamp += 86400);
+            client.delegate_to_pool(&alice, &pool_id, &1000);
+        }
+
+        let alice_pending = client.calculate_pending_rewards(&alice, &pool_id);
+        assert_eq!(alice_pending, 12);
+        assert_eq!(alice_pending, client.calculate_pending_rewards(&bob, &pool_id));
+    }
+
+    #[test]
+    fn test_create_pool_with_high_precision_rate() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let token = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        // 3,333% ao ano não cabe em pontos base
+        let pool_id = client.create_pool_with_rate(
+            &owner, &token, &token, &1_000_000_000, &33_330_000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
         );
-        
-        assert_eq!(pool_id, 1);
-        
-        // Verificar pool criada
         let pool = client.get_pool(&pool_id);
-        assert_eq!(pool.owner, owner);
-        assert_eq!(pool.total_rewards, 1000000);
-        assert_eq!(pool.max_apy, 1500);
-        assert_eq!(pool.distribution_days, 30);
-        assert!(pool.is_active);
+        assert_eq!((pool.apy_rate, pool.max_apy), (33_330_000, 333));
+
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 33_330);
     }
 
     #[test]
-    fn test_delegate_and_claim() {
+    fn test_compounding_accrual_matches_advertised_apy() {
         let env = Env::default();
+        env.mock_all_auths();
         let contract_id = env.register_contract(None, PoolRewardsContract);
         let client = PoolRewardsContractClient::new(&env, &contract_id);
-        
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&owner, 2_000_000_000)]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &kale, &2_000_000_000, &1000, &730, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.set_accrual_model(&pool_id, &AccrualModel::Compounding);
+        client.deposit_rewards(&owner, &pool_id, &2_000_000_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000_000);
+        client.delegate_to_pool(&bob, &pool_id, &1_000_000_000);
+
+        // Alice reinveste todo dia pelo contrato; Bob só acumula.
+        // Cada reinvestimento é uma transação própria, com orçamento próprio.
+        for _ in 0..365 {
+            env.ledger().with_mut(|li| li.timestamp += 86400);
+            env.budget().reset_default();
+            client.compound(&alice, &pool_id);
+        }
+
+        // Reinvestindo todo dia, o stake cresce os 10% anunciados
+        let delegation = client.get_user_delegation(&alice, &pool_id).unwrap();
+        let stake = delegation.amount + delegation.compounded;
+        assert!((stake - 1_100_000_000).abs() < 10_000);
+
+        // Sem reinvestir, 10% compostos equivalem a ~9,53% simples: (1,1^(1/365) - 1) * 365
+        let pending = client.calculate_pending_rewards(&bob, &pool_id);
+        assert!(pending > 95_322_000 && pending < 95_323_000);
+    }
+
+    #[test]
+    fn test_compound_and_auto_compound() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&owner, 1_000_000)]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &kale, &1_000_000, &1000, &730, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &1_000_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        client.delegate_to_pool(&bob, &pool_id, &1_000_000);
+        client.set_auto_compound(&bob, &pool_id, &true);
+
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+
+        // Alice reinveste manualmente; o keeper reinveste só para quem ligou a opção
+        assert_eq!(client.compound(&alice, &pool_id), 100_000);
+        assert_eq!(client.auto_compound(&pool_id, &vec![&env, alice.clone(), bob.clone()]), 100_000);
+
+        let delegation = client.get_user_delegation(&alice, &pool_id).unwrap();
+        assert_eq!((delegation.amount, delegation.compounded, delegation.accrued), (1_000_000, 100_000, 0));
+        let pool = client.get_pool(&pool_id);
+        assert_eq!(pool.total_delegated, 2_200_000);
+        assert_eq!((pool.escrowed, pool.restaked), (800_000, 200_000));
+
+        // O stake reinvestido também rende e pode ser sacado
+        env.ledger().with_mut(|li| li.timestamp += 365 * 86400);
+        assert_eq!(client.calculate_pending_rewards(&alice, &pool_id), 110_000);
+        assert_eq!(client.withdraw_compounded(&alice, &pool_id), 100_000);
+        assert_eq!(token::Client::new(&env, &kale).balance(&alice), 100_000);
+        assert_eq!(client.get_pool(&pool_id).total_delegated, 2_100_000);
+    }
+
+    #[test]
+    fn test_multiple_reward_streams() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&owner, 1_000_000)]);
+        let usdc = create_token(&env, &admin, &[(&owner, 100_000)]);
+        client.initialize(&admin);
+
+        // KALE pelo fluxo principal (APY alto para não limitar) e USDC por um fluxo adicional
+        let pool_id = client.create_pool(
+            &owner, &kale, &kale, &30_000, &10000, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &30_000);
+        client.delegate_to_pool(&alice, &pool_id, &1_000_000);
+        assert_eq!(client.add_reward_stream(&owner, &pool_id, &usdc, &60_000, &EmissionSchedule::Constant), 0);
+
+        // Bob entra no dia 10 e não recebe o que foi acumulado antes
+        env.ledger().with_mut(|li| li.timestamp += 10 * 86400);
+        client.delegate_to_pool(&bob, &pool_id, &1_000_000);
+        env.ledger().with_mut(|li| li.timestamp += 10 * 86400);
+
+        let alice_paid = client.claim_rewards(&alice, &pool_id);
+        assert_eq!(alice_paid.get(kale.clone()), Some(15_000));
+        assert_eq!(alice_paid.get(usdc.clone()), Some(30_000));
+        let bob_pending = client.get_pending_rewards_by_token(&bob, &pool_id);
+        assert_eq!(bob_pending.get(usdc.clone()), Some(10_000));
+
+        // Recarga dobra a emissão restante; encerrar devolve o que não foi atribuído
+        client.top_up_stream(&owner, &pool_id, &0, &20_000);
+        env.ledger().with_mut(|li| li.timestamp += 5 * 86400);
+        assert_eq!(client.end_stream(&owner, &pool_id, &0), 20_000);
+
+        assert_eq!(client.claim_rewards(&bob, &pool_id).get(usdc.clone()), Some(20_000));
+        assert_eq!(token::Client::new(&env, &usdc).balance(&alice), 30_000);
+        let stream = client.get_pool(&pool_id).streams.get(0).unwrap();
+        assert_eq!((stream.emitted, stream.distributed, stream.escrowed), (60_000, 50_000, 10_000));
+    }
+
+    #[test]
+    fn test_sponsor_pool_refunds_unused_pro_rata() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
         let admin = Address::generate(&env);
         let owner = Address::generate(&env);
+        let sponsor = Address::generate(&env);
         let user = Address::generate(&env);
-        let token = Address::generate(&env);
-        
-        // Inicializar e criar pool
+        let kale = create_token(&env, &admin, &[(&user, 1_000_000)]);
+        let usdc = create_token(&env, &admin, &[(&owner, 60_000), (&sponsor, 40_000)]);
+        let usdc_client = token::Client::new(&env, &usdc);
+
         client.initialize(&admin);
-        let pool_id = client.create_pool(&owner, &token, &1000000, &1500, &30);
-        
-        // Usuário delega tokens
-        client.delegate_to_pool(&user, &pool_id, &10000);
-        
-        // Verificar delegação
-        let delegation = client.get_user_delegation(&user, &pool_id).unwrap();
-        assert_eq!(delegation.amount, 10000);
-        assert_eq!(delegation.user, user);
-        
-        // Simular passagem de tempo e calcular recompensas
-        // (Em um teste real, seria necessário manipular o timestamp)
-        let pending = client.calculate_pending_rewards(&user, &pool_id);
-        // As recompensas dependem do tempo, então apenas verificamos que não há erro
-        assert!(pending >= 0);
+        let pool_id = client.create_pool(&owner, &kale, &usdc, &100_000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &pool_id, &60_000);
+        client.sponsor_pool(&sponsor, &pool_id, &40_000);
+        let event = env.events().all().last().unwrap();
+        assert_eq!(event.1, (symbol_short!("sponsor"), pool_id).into_val(&env));
+        let data: (Address, i128, u32) = TryFromVal::try_from_val(&env, &event.2).unwrap();
+        assert_eq!(data, (sponsor.clone(), 40_000, 7));
+        assert_eq!(client.get_sponsors(&pool_id).get(sponsor.clone()), Some(40_000));
+        client.opt_in(&user, &pool_id);
+        client.sync_delegation(&user, &pool_id);
+
+        // 40% das sobras voltam para o patrocinador na finalização
+        env.ledger().with_mut(|l| l.timestamp += 40 * 86400);
+        let earned = 1_000_000 * 1500 / 10000 * 30 / 365;
+        assert_eq!(client.finalize_pool(&pool_id), earned + 1);
+        let unused = 100_000 - (earned + 1);
+        assert_eq!(usdc_client.balance(&sponsor), unused * 40 / 100);
+
+        env.ledger().with_mut(|l| l.timestamp += 7 * 86400);
+        assert_eq!(client.withdraw_unused_rewards(&owner, &pool_id), unused - unused * 40 / 100);
+        assert_eq!(client.claim_rewards(&user, &pool_id).get(usdc.clone()), Some(earned));
+        assert_eq!(client.get_pool(&pool_id).escrowed, 1);
+    }
+
+    #[test]
+    fn test_protocol_fee_on_claims() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let user = Address::generate(&env);
+        let treasury = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[(&user, 1_000_000)]);
+        let usdc = create_token(&env, &admin, &[(&owner, 100_000)]);
+        let usdc_client = token::Client::new(&env, &usdc);
+
+        client.initialize(&admin);
+        client.set_protocol_fee(&admin, &250);
+        let pool_id = client.create_pool(&owner, &kale, &usdc, &100_000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &pool_id, &100_000);
+        client.opt_in(&user, &pool_id);
+        client.sync_delegation(&user, &pool_id);
+
+        // 2,5% de cada resgate fica com o protocolo
+        env.ledger().with_mut(|l| l.timestamp += 10 * 86400);
+        let earned = 1_000_000 * 1500 / 10000 * 10 / 365;
+        let fee = earned * 250 / 10000;
+        assert_eq!(client.claim_rewards(&user, &pool_id).get(usdc.clone()), Some(earned - fee));
+        assert_eq!(usdc_client.balance(&user), earned - fee);
+        assert_eq!(client.get_protocol_fees_accrued(&usdc), fee);
+        assert_eq!(client.get_pool(&pool_id).distributed_amount, earned);
+
+        assert_eq!(client.withdraw_protocol_fees(&admin, &usdc, &treasury), fee);
+        assert_eq!(usdc_client.balance(&treasury), fee);
+        assert_eq!(client.get_protocol_fees_accrued(&usdc), 0);
+        assert_eq!(client.withdraw_protocol_fees(&admin, &usdc, &treasury), 0);
+
+        // Reinvestir também paga a taxa: só o líquido vira stake e pode ser sacado
+        let kale_client = token::Client::new(&env, &kale);
+        kale_client.transfer(&user, &owner, &100_000);
+        let kale_pool = client.create_pool(&owner, &kale, &kale, &200_000, &1000, &730, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &kale_pool, &100_000);
+        client.delegate_to_pool(&user, &kale_pool, &900_000);
+        env.ledger().with_mut(|l| l.timestamp += 365 * 86400);
+        assert_eq!(client.compound(&user, &kale_pool), 90_000 - 2_250);
+        assert_eq!(client.get_protocol_fees_accrued(&kale), 2_250);
+        assert_eq!(client.withdraw_compounded(&user, &kale_pool), 87_750);
+    }
+
+    #[test]
+    fn test_claim_limited_to_pool_escrow() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let other = Address::generate(&env);
+        let user = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[(&owner, 1_000), (&other, 100_000)]);
+        let usdc_client = token::Client::new(&env, &usdc);
+        client.initialize(&admin);
+
+        // Outra pool bem financiada no mesmo token de recompensa
+        let funded = client.create_pool(&other, &kale, &usdc, &100_000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&other, &funded, &100_000);
+
+        // Pool com orçamento anunciado de 50000, mas só 1000 depositados
+        let pool_id = client.create_pool(&owner, &kale, &usdc, &50_000, &1500, &30, &None, &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity);
+        client.deposit_rewards(&owner, &pool_id, &1_000);
+        client.delegate_to_pool(&user, &pool_id, &1_000_000);
+        env.ledger().with_mut(|l| l.timestamp += 10 * 86400);
+
+        // Paga só o custodiado; o restante segue acumulado e a outra pool não é tocada
+        let earned = 1_000_000 * 1500 / 10000 * 10 / 365;
+        assert_eq!(client.claim_rewards(&user, &pool_id).get(usdc.clone()), Some(1_000));
+        assert_eq!(client.calculate_pending_rewards(&user, &pool_id), earned - 1_000);
+        assert_eq!(client.claim_rewards(&user, &pool_id).len(), 0);
+        assert_eq!(client.get_pool(&pool_id).escrowed, 0);
+        assert_eq!(usdc_client.balance(&contract_id), 100_000);
+    }
+
+    #[test]
+    fn test_finalize_merkle_pool_reserves_escrow_until_claim_window() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[(&owner, 10_000)]);
+        let usdc_client = token::Client::new(&env, &usdc);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &usdc, &10_000, &1500, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Merkle, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &pool_id, &10_000);
+        let a1 = merkle_leaf(&env, pool_id, &alice, 4_000);
+        let b1 = merkle_leaf(&env, pool_id, &bob, 6_000);
+        client.post_merkle_root(&owner, &pool_id, &1, &merkle_node(&env, &a1, &b1));
+
+        // Na finalização todo o custodiado fica reservado para provas ainda não resgatadas
+        env.ledger().with_mut(|l| l.timestamp += 31 * 86400);
+        assert_eq!(client.finalize_pool(&pool_id), 10_000);
+        env.ledger().with_mut(|l| l.timestamp += 7 * 86400);
+        assert_eq!(client.claim_merkle(&alice, &pool_id, &4_000, &vec![&env, b1]), 4_000);
+        assert_eq!(client.get_pool(&pool_id).reserved_rewards, 6_000);
+
+        // Só ao fim da janela de resgate o dono retira o que não foi resgatado
+        env.ledger().with_mut(|l| l.timestamp += 83 * 86400);
+        assert_eq!(client.withdraw_unused_rewards(&owner, &pool_id), 6_000);
+        assert_eq!(usdc_client.balance(&owner), 6_000);
+        assert_eq!(client.get_pool(&pool_id).escrowed, 0);
+    }
+
+    #[test]
+    fn test_pool_entries_outlive_minimum_ttl() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        client.initialize(&admin);
+
+        let pool_id = client.create_pool(
+            &owner, &kale, &kale, &1_000_000, &1000, &365, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Continuous, &ConversionRate::Parity,
+        );
+
+        // Pool e índices são renovados na gravação e seguem legíveis bem depois do TTL mínimo
+        env.as_contract(&contract_id, || env.storage().instance().extend_ttl(PERSISTENT_TTL_EXTEND, PERSISTENT_TTL_EXTEND));
+        env.ledger().with_mut(|li| li.sequence_number += 60 * 17_280);
+        assert_eq!(client.get_pool(&pool_id).id, pool_id);
+        let filters = vec![&env, PoolFilter::Owner(owner.clone())];
+        assert_eq!(pool_ids(&env, client.list_pools(&0, &10, &filters).0), vec![&env, pool_id]);
+    }
+
+    #[test]
+    fn test_cancel_merkle_pool_only_before_root() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, PoolRewardsContract);
+        let client = PoolRewardsContractClient::new(&env, &contract_id);
+
+        let admin = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let kale = create_token(&env, &admin, &[]);
+        let usdc = create_token(&env, &admin, &[(&owner, 20_000)]);
+        client.initialize(&admin);
+
+        let published = client.create_pool(
+            &owner, &kale, &usdc, &10_000, &1500, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Merkle, &ConversionRate::Parity,
+        );
+        let unpublished = client.create_pool(
+            &owner, &kale, &usdc, &10_000, &1500, &30, &None,
+            &EmissionSchedule::Constant, &DistributionMode::Merkle, &ConversionRate::Parity,
+        );
+        client.deposit_rewards(&owner, &published, &10_000);
+        client.deposit_rewards(&owner, &unpublished, &10_000);
+        let a1 = merkle_leaf(&env, published, &alice, 4_000);
+        let b1 = merkle_leaf(&env, published, &bob, 6_000);
+        client.post_merkle_root(&owner, &published, &1, &merkle_node(&env, &a1, &b1));
+
+        // Sem raiz publicada, a pool ainda pode ser cancelada depois do início; com a raiz,
+        // o custodiado já pertence aos usuários e o cancelamento é recusado
+        env.ledger().with_mut(|l| l.timestamp += 20 * 86400);
+        assert_eq!(client.claim_merkle(&alice, &published, &4_000, &vec![&env, b1]), 4_000);
+        assert_eq!(client.cancel_pool(&owner, &unpublished), 10_000);
+        assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 10_000);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,293 @@
+// Aritmética de ponto fixo sem overflow intermediário.
+// Produtos são calculados em 256 bits quando não cabem em i128, e o arredondamento é sempre
+// explícito: quem chama escolhe o sentido que favorece a pool.
+
+// Sentido do arredondamento da divisão
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    // Em direção a -infinito
+    Floor,
+    // Em direção a +infinito
+    Ceil,
+}
+
+// Taxa de alta precisão, escalada por Rate::SCALE (1.0 = 1e18)
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub struct Rate(i128);
+
+impl Rate {
+    pub const SCALE: i128 = 1_000_000_000_000_000_000;
+    pub const ONE: Rate = Rate(Self::SCALE);
+
+    pub const fn from_raw(raw: i128) -> Self {
+        Rate(raw)
+    }
+
+    pub const fn raw(self) -> i128 {
+        self.0
+    }
+
+    // Taxa expressa em outra escala (ex.: 1e9 = 100%), arredondada para baixo
+    pub fn from_scaled(value: i128, scale: i128) -> Self {
+        Rate(mul_div(value, Self::SCALE, scale, Rounding::Floor))
+    }
+
+    // Aplica a taxa a um valor: amount * taxa
+    pub fn apply(self, amount: i128, rounding: Rounding) -> i128 {
+        mul_div(amount, self.0, Self::SCALE, rounding)
+    }
+
+    // Potência inteira por quadrados sucessivos, arredondando cada produto para baixo
+    pub fn pow(self, mut exp: u32) -> Rate {
+        let mut base = self;
+        let mut result = Rate::ONE;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = Rate(base.apply(result.0, Rounding::Floor));
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = Rate(base.apply(base.0, Rounding::Floor));
+            }
+        }
+        result
+    }
+}
+
+// Taxa por período que, composta `periods` vezes, não passa de `annual`:
+// maior g tal que g^periods <= 1 + annual, retornando g - 1.
+pub fn periodic_rate(annual: Rate, periods: u32) -> Rate {
+    let target = Rate(Rate::SCALE + annual.0);
+    // Por Bernoulli, (1 + annual/periods)^periods >= 1 + annual, o que limita a busca
+    let (mut low, mut high) = (Rate::SCALE, Rate::SCALE + annual.0 / periods as i128 + 1);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if Rate(mid).pow(periods) <= target {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Rate(low - Rate::SCALE)
+}
+
+// a * b / denominator com o arredondamento pedido; aborta se o resultado não couber em i128
+pub fn mul_div(a: i128, b: i128, denominator: i128, rounding: Rounding) -> i128 {
+    checked_mul_div(a, b, denominator, rounding).expect("Math overflow")
+}
+
+pub fn mul_div_floor(a: i128, b: i128, denominator: i128) -> i128 {
+    mul_div(a, b, denominator, Rounding::Floor)
+}
+
+pub fn mul_div_ceil(a: i128, b: i128, denominator: i128) -> i128 {
+    mul_div(a, b, denominator, Rounding::Ceil)
+}
+
+// a * b / denominator sem overflow intermediário. None se o denominador for zero ou se o
+// resultado não couber em i128.
+pub fn checked_mul_div(a: i128, b: i128, denominator: i128, rounding: Rounding) -> Option<i128> {
+    if denominator == 0 {
+        return None;
+    }
+    let negative = (a < 0) ^ (b < 0) ^ (denominator < 0);
+    let (quotient, remainder) = mul_div_u128(a.unsigned_abs(), b.unsigned_abs(), denominator.unsigned_abs())?;
+
+    // Arredonda a magnitude para cima quando o sentido pedido se afasta de zero
+    let away_from_zero = match rounding {
+        Rounding::Floor => negative,
+        Rounding::Ceil => !negative,
+    };
+    let quotient = if remainder != 0 && away_from_zero { quotient.checked_add(1)? } else { quotient };
+
+    if negative {
+        if quotient > i128::MIN.unsigned_abs() {
+            return None;
+        }
+        Some((quotient as i128).wrapping_neg())
+    } else {
+        i128::try_from(quotient).ok()
+    }
+}
+
+// (a * b + carry) / denominator arredondado para baixo, junto com o resto.
+// Só para valores não negativos, com carry menor que o denominador.
+pub fn mul_div_rem(a: i128, b: i128, carry: i128, denominator: i128) -> (i128, i128) {
+    assert!(a >= 0 && b >= 0 && carry >= 0 && carry < denominator, "Invalid mul_div_rem operands");
+    let (hi, lo) = full_mul(a as u128, b as u128);
+    let (lo, overflow) = lo.overflowing_add(carry as u128);
+    let hi = hi + overflow as u128;
+    let d = denominator as u128;
+    let (quotient, remainder) = if hi == 0 { (lo / d, lo % d) } else { div_wide(hi, lo, d).expect("Math overflow") };
+    (i128::try_from(quotient).expect("Math overflow"), remainder as i128)
+}
+
+// Quociente e resto de a * b / d em 256 bits
+fn mul_div_u128(a: u128, b: u128, d: u128) -> Option<(u128, u128)> {
+    if let Some(product) = a.checked_mul(b) {
+        return Some((product / d, product % d));
+    }
+    let (hi, lo) = full_mul(a, b);
+    div_wide(hi, lo, d)
+}
+
+// Produto completo de 256 bits como (alto, baixo)
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a1, a0) = (a >> 64, a & MASK);
+    let (b1, b0) = (b >> 64, b & MASK);
+
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let lo = (p00 & MASK) | (mid << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+// Divisão longa de (alto, baixo) por d. None se o quociente não couber em 128 bits.
+fn div_wide(hi: u128, lo: u128, d: u128) -> Option<(u128, u128)> {
+    if hi >= d {
+        return None;
+    }
+    let mut remainder = hi;
+    let mut quotient = 0u128;
+    for i in (0..128).rev() {
+        // O resto pode passar de 128 bits por um bit antes da subtração
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> i) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            quotient |= 1;
+        }
+    }
+    Some((quotient, remainder))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Gerador pseudoaleatório determinístico (xorshift) para os testes de propriedade
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // Valor com magnitude de 0 a 127 bits, cobrindo extremos
+        fn i128(&mut self) -> i128 {
+            let raw = ((self.next() as u128) << 64 | self.next() as u128) >> (self.next() % 128);
+            let value = (raw >> 1) as i128;
+            if self.next() % 2 == 0 { value } else { -value }
+        }
+    }
+
+    #[test]
+    fn test_matches_exact_arithmetic_when_product_fits() {
+        let mut rng = XorShift(0x9e3779b97f4a7c15);
+        for _ in 0..5000 {
+            let (a, b, d) = (rng.i128() >> 64, rng.i128() >> 64, rng.i128() >> 64);
+            if d == 0 {
+                continue;
+            }
+            let product = a * b;
+            let floor = product.div_euclid(d) - if d < 0 && product.rem_euclid(d) != 0 { 1 } else { 0 };
+            assert_eq!(mul_div_floor(a, b, d), floor);
+            let exact = product % d == 0;
+            assert_eq!(mul_div_ceil(a, b, d), if exact { floor } else { floor + 1 });
+        }
+    }
+
+    #[test]
+    fn test_floor_and_ceil_bracket_the_result() {
+        let mut rng = XorShift(0x2545f4914f6cdd1d);
+        for _ in 0..5000 {
+            let (a, b, d) = (rng.i128(), rng.i128(), rng.i128());
+            let floor = checked_mul_div(a, b, d, Rounding::Floor);
+            let ceil = checked_mul_div(a, b, d, Rounding::Ceil);
+            if let (Some(floor), Some(ceil)) = (floor, ceil) {
+                assert!(ceil == floor || ceil == floor + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_products_do_not_overflow() {
+        assert_eq!(mul_div_floor(i128::MAX, i128::MAX, i128::MAX), i128::MAX);
+        assert_eq!(mul_div_floor(i128::MIN, 1, 1), i128::MIN);
+        assert_eq!(mul_div_floor(1 << 100, 1 << 100, 1 << 90), 1 << 110);
+        assert_eq!(mul_div_floor(i128::MAX, 3, 7), i128::MAX / 7 * 3);
+        assert_eq!(checked_mul_div(i128::MAX, 2, 1, Rounding::Floor), None);
+        assert_eq!(checked_mul_div(1, 1, 0, Rounding::Floor), None);
+    }
+
+    #[test]
+    fn test_remainder_carry_is_independent_of_splits() {
+        // Somar parcelas carregando o resto equivale a uma única divisão do total
+        let mut rng = XorShift(0xdeadbeefcafef00d);
+        for _ in 0..1000 {
+            let amount = (rng.next() >> 1) as i128;
+            let mut paid = 0i128;
+            let mut carry = 0i128;
+            let mut total_delta = 0i128;
+            for _ in 0..10 {
+                let delta = (rng.next() % 1_000_000) as i128;
+                let (q, r) = mul_div_rem(amount, delta, carry, 1_000_000_000_000);
+                paid += q;
+                carry = r;
+                total_delta += delta;
+            }
+            assert_eq!(paid, mul_div_floor(amount, total_delta, 1_000_000_000_000));
+        }
+    }
+
+    #[test]
+    fn test_periodic_rate_compounds_back_to_annual() {
+        let mut rng = XorShift(0x0123456789abcdef);
+        for _ in 0..50 {
+            let annual = Rate((rng.next() % Rate::SCALE as u64) as i128);
+            for periods in [1u32, 12, 365] {
+                let periodic = periodic_rate(annual, periods);
+                let compounded = Rate(Rate::SCALE + periodic.0).pow(periods);
+                // Nunca passa do anual; o erro fica abaixo de 1e-12
+                assert!(compounded.0 <= Rate::SCALE + annual.0);
+                assert!(Rate::SCALE + annual.0 - compounded.0 < 1_000_000);
+            }
+        }
+        assert_eq!(periodic_rate(Rate::from_scaled(10, 100), 1), Rate::from_scaled(10, 100));
+    }
+
+    #[test]
+    fn test_rounding_favors_the_requested_side() {
+        assert_eq!(mul_div_floor(7, 1, 2), 3);
+        assert_eq!(mul_div_ceil(7, 1, 2), 4);
+        assert_eq!(mul_div_floor(-7, 1, 2), -4);
+        assert_eq!(mul_div_ceil(-7, 1, 2), -3);
+    }
+
+    #[test]
+    fn test_rate_over_extreme_balances_and_durations() {
+        // 100% ao ano por 1000 anos sobre o maior saldo que ainda cabe no resultado
+        let apy = Rate::from_scaled(10000, 10000);
+        let seconds = 1000 * 365 * 86400i128;
+        let balance = i128::MAX / 1000;
+        let earned = mul_div_floor(apy.apply(balance, Rounding::Floor), seconds, 365 * 86400);
+        assert_eq!(earned, balance * 1000);
+
+        let bps = Rate::from_scaled(3333, 10000);
+        assert_eq!(bps.apply(-10_001, Rounding::Floor), -3334);
+        assert_eq!(bps.apply(-10_001, Rounding::Ceil), -3333);
+        assert_eq!(bps.apply(10_001, Rounding::Ceil), 3334);
+        assert_eq!(Rate::from_scaled(1500, 10000), Rate(Rate::SCALE * 15 / 100));
+        assert_eq!(Rate::from_scaled(33_330_000, 1_000_000_000), Rate(Rate::SCALE / 100_000 * 3333));
+    }
+}